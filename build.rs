@@ -23,11 +23,21 @@ fn main() {
         .display()
         .to_string();
 
-    let mut config = Config::default();
-    config.include_guard = Some("__INTERNAL_IMAGE_LOAD_H".to_owned());
-    config.language = Language::C;
-    config.namespace = None;
-    config.export = ExportConfig::default();
+    let config = Config {
+        include_guard: Some("__INTERNAL_IMAGE_LOAD_H".to_owned()),
+        language: Language::C,
+        namespace: None,
+        export: ExportConfig {
+            // Only handed to C through the void pointers of RawIterator, so cbindgen can't find it on its own.
+            include: vec![
+                "FrameResult".to_owned(),
+                "PixelResult16".to_owned(),
+                "PixelResult32F".to_owned(),
+            ],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
 
     cbindgen::generate_with_config(&crate_dir, config)
         .unwrap()
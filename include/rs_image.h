@@ -6,6 +6,41 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+/**
+ * How dynamic_image_blend combines the colors of the top layer with the ones under it.
+ * These follow the separable blend modes of the W3C compositing spec.
+ */
+typedef enum BlendMode {
+  BLEND_MODE_NORMAL,
+  BLEND_MODE_MULTIPLY,
+  BLEND_MODE_SCREEN,
+  BLEND_MODE_OVERLAY,
+  BLEND_MODE_DARKEN,
+  BLEND_MODE_LIGHTEN,
+  /**
+   * Clamped to white.
+   */
+  BLEND_MODE_ADD,
+  BLEND_MODE_DIFFERENCE,
+} BlendMode;
+
+/**
+ * Which values the _with_color_space variants of the resampling and filtering functions work on.
+ */
+typedef enum ColorSpaceMode {
+  /**
+   * The encoded values as stored, which is what the plain functions do. Fast, but averaging gamma
+   * encoded values darkens fine detail and high-contrast edges.
+   */
+  COLOR_SPACE_MODE_GAMMA,
+  /**
+   * Decodes the sRGB transfer function first, works on linear light in f32, and encodes the result
+   * back into the original color type. Rgb32F and Rgba32F images are taken to hold linear light already,
+   * the way the HDR and OpenEXR decoders produce them, so only their processing is done in f32.
+   */
+  COLOR_SPACE_MODE_LINEAR,
+} ColorSpaceMode;
+
 typedef enum ColorType {
   COLOR_TYPE_L8,
   COLOR_TYPE_LA8,
@@ -19,6 +54,24 @@ typedef enum ColorType {
   COLOR_TYPE_RGBA32F,
 } ColorType;
 
+/**
+ * Which encoder an EncodeOptions struct is for, and so which field of its union is valid.
+ */
+typedef enum EncodeOptionsType {
+  ENCODE_OPTIONS_JPEG,
+  ENCODE_OPTIONS_PNG,
+  /**
+   * WebP is only encoded losslessly, so there are no options for it.
+   */
+  ENCODE_OPTIONS_WEBP,
+  ENCODE_OPTIONS_AVIF,
+  ENCODE_OPTIONS_PNM,
+  /**
+   * TIFF has no options, but is here so that it can be written like the others.
+   */
+  ENCODE_OPTIONS_TIFF,
+} EncodeOptionsType;
+
 typedef enum ErrorType {
   IMAGE_ERROR_NONE,
   IMAGE_ERROR_DECODING,
@@ -54,8 +107,29 @@ typedef enum ErrorType {
   IMAGE_ERROR_IO_OUT_OF_MEMORY,
   IMAGE_ERROR_IO_OTHER,
   IMAGE_ERROR_UNKNOWN,
+  /**
+   * The library panicked while handling the call. This is a bug in rs_image, the image crate, or the arguments passed in.
+   */
+  IMAGE_ERROR_PANIC,
 } ErrorType;
 
+/**
+ * Which directory of the EXIF data a tag was found in.
+ */
+typedef enum ExifIfd {
+  /**
+   * IFD0, describing the main image.
+   */
+  EXIF_IFD_PRIMARY,
+  /**
+   * IFD1, describing the embedded thumbnail.
+   */
+  EXIF_IFD_THUMBNAIL,
+  EXIF_IFD_EXIF,
+  EXIF_IFD_GPS,
+  EXIF_IFD_INTEROP,
+} ExifIfd;
+
 typedef enum ExtendedColorType {
   EXTENDED_COLOR_TYPE_A8,
   EXTENDED_COLOR_TYPE_L1,
@@ -83,6 +157,7 @@ typedef enum ExtendedColorType {
   EXTENDED_COLOR_TYPE_RGB32F,
   EXTENDED_COLOR_TYPE_RGBA32F,
   EXTENDED_COLOR_TYPE_CMYK8,
+  EXTENDED_COLOR_TYPE_UNKNOWN,
 } ExtendedColorType;
 
 typedef enum FilterType {
@@ -117,6 +192,62 @@ typedef enum Ordering {
   ORDERING_GREATER,
 } Ordering;
 
+/**
+ * Compression level of the PNG encoder.
+ */
+typedef enum PngCompressionType {
+  PNG_COMPRESSION_DEFAULT,
+  PNG_COMPRESSION_FAST,
+  PNG_COMPRESSION_BEST,
+} PngCompressionType;
+
+/**
+ * Filter algorithm the PNG encoder runs over each scanline before compressing it.
+ */
+typedef enum PngFilterType {
+  PNG_FILTER_NO_FILTER,
+  PNG_FILTER_SUB,
+  PNG_FILTER_UP,
+  PNG_FILTER_AVG,
+  PNG_FILTER_PAETH,
+  PNG_FILTER_ADAPTIVE,
+} PngFilterType;
+
+/**
+ * Which of the PNM formats to write.
+ */
+typedef enum PnmSubtype {
+  /**
+   * P1 or P4
+   */
+  PNM_SUBTYPE_BITMAP,
+  /**
+   * P2 or P5
+   */
+  PNM_SUBTYPE_GRAYMAP,
+  /**
+   * P3 or P6
+   */
+  PNM_SUBTYPE_PIXMAP,
+  /**
+   * P7
+   */
+  PNM_SUBTYPE_ARBITRARY_MAP,
+} PnmSubtype;
+
+/**
+ * Which ICC rendering intent dynamic_image_convert_profile uses.
+ *
+ * Matrix/TRC profiles only define a colorimetric mapping, so perceptual and saturation behave like relative
+ * colorimetric. Absolute colorimetric additionally scales by the ratio of the two media white points.
+ */
+typedef enum RenderingIntent {
+  RENDERING_INTENT_PERCEPTUAL,
+  RENDERING_INTENT_RELATIVE_COLORIMETRIC,
+  RENDERING_INTENT_SATURATION,
+  RENDERING_INTENT_ABSOLUTE_COLORIMETRIC,
+} RenderingIntent;
+
 /**
  * Type used for the SeekFrom struct
  */
@@ -128,6 +259,10 @@ typedef enum SeekType {
 
 typedef struct DynamicImage {
   void *inner;
+  /**
+   * The ICC profile the image was loaded with, as a Box<Vec<u8>>, or NULL if it has none.
+   */
+  void *icc;
 } DynamicImage;
 
 typedef struct LoadFromMemoryResult {
@@ -135,6 +270,20 @@ typedef struct LoadFromMemoryResult {
   enum ErrorType err;
 } LoadFromMemoryResult;
 
+/**
+ * Limits the decoder has to stay within, mirroring the image crate's Limits struct.
+ * A value of 0 means that there is no limit for that field.
+ */
+typedef struct DecodeLimits {
+  uint32_t max_image_width;
+  uint32_t max_image_height;
+  /**
+   * The total amount of bytes the decoder may allocate over the whole decode, including the output image.
+   * Unlike the other loaders, which keep the image crate's default cap of 512 MiB, 0 leaves this unlimited.
+   */
+  uint64_t max_alloc;
+} DecodeLimits;
+
 /**
  * Union used for the SeekFrom struct
  */
@@ -162,6 +311,24 @@ typedef struct SeekFrom {
   union SeekUnion val;
 } SeekFrom;
 
+/**
+ * Struct that contains function pointers that correspond to both Rust's Read trait and it's Seek trait.
+ * Like RustWriter, you put the pointer for your custom own struct in the user_data field,
+ * then pass your own functions which will then access that user data.
+ *
+ * read_fn should copy at most buf_size bytes into buf and return how many were copied, returning 0 once there is nothing left to read
+ * and -1 if reading failed. A failed read is reported as IMAGE_ERROR_IO_OTHER.
+ *
+ * Only Read and Seek are implemented, not BufRead. BufRead needs a buffer owned by the reader, which a struct
+ * that C fills in with nothing but callbacks has no room for, so the functions taking a RustReader wrap it in a
+ * std::io::BufReader instead. That way read_fn is called with reasonably large chunks rather than a byte at a time.
+ */
+typedef struct RustReader {
+  void *user_data;
+  intptr_t (*read_fn)(void *ud, uint8_t *buf, uintptr_t buf_size);
+  uint64_t (*seek_fn)(void *ud, struct SeekFrom pos);
+} RustReader;
+
 /**
  * Struct that contains function pointers that correspond to both Rust's Write trait and it's Seek trait.
  * The idea here is that you put the pointer for your custom own struct in the user_data field,
@@ -194,6 +361,19 @@ typedef struct Dimensions {
   uint32_t height;
 } Dimensions;
 
+/**
+ * What dynamic_image_probe found out about an image.
+ *
+ * format, dimensions and color_type are unspecified unless err is IMAGE_ERROR_NONE. In particular a failed probe
+ * can leave format reading as IMAGE_FORMAT_PNG, so always check err first.
+ */
+typedef struct ProbeResult {
+  enum ImageFormat format;
+  struct Dimensions dimensions;
+  enum ExtendedColorType color_type;
+  enum ErrorType err;
+} ProbeResult;
+
 typedef struct Rgba {
   uint8_t r;
   uint8_t g;
@@ -203,6 +383,7 @@ typedef struct Rgba {
 
 typedef struct ThinIteratorVtable {
   void *(*next)(void*);
+  void (*free_item)(void*, void*);
   void (*drop)(void*);
 } ThinIteratorVtable;
 
@@ -214,6 +395,11 @@ typedef struct ThinIteratorVtable *BoxedThinIterator;
  * `__s` is expected to a pointer to something that implements Rust's std::iter::Iterator. You should not try and instantiate this yourself unless you have an object from Rust code.
  *
  * To make use of this, you should use the appropriate `iter_...` function.
+ *
+ * Every iterator you're handed has to be released with iter_free once you're done with it, and every item it
+ * yields with iter_item_free. Adaptors like iter_map or iter_zip copy the iterators they're built from rather than
+ * taking them over, so those still need freeing after the adaptor is done. Items from an adaptor are boxes around
+ * the items of the iterator underneath, and freeing one doesn't free what it wraps.
  */
 typedef struct RawIterator {
   BoxedThinIterator *__s;
@@ -231,16 +417,265 @@ typedef struct SizeHint {
   uintptr_t *rhs;
 } SizeHint;
 
+typedef struct FramesResult {
+  struct RawIterator res;
+  enum ErrorType err;
+} FramesResult;
+
+/**
+ * Handle for writing an animated GIF one frame at a time. Get one from gif_encoder_new.
+ */
+typedef struct GifEncoder {
+  void *inner;
+} GifEncoder;
+
+typedef struct JpegOptions {
+  /**
+   * From 1 to 100, where 100 is the best quality.
+   */
+  uint8_t quality;
+} JpegOptions;
+
+typedef struct PngOptions {
+  enum PngCompressionType compression;
+  enum PngFilterType filter;
+} PngOptions;
+
+typedef struct AvifOptions {
+  /**
+   * From 1 to 10, where 1 is the slowest and smallest.
+   */
+  uint8_t speed;
+  /**
+   * From 1 to 100, where 100 is the best quality.
+   */
+  uint8_t quality;
+} AvifOptions;
+
+typedef struct PnmOptions {
+  enum PnmSubtype subtype;
+  /**
+   * Whether samples are written as ASCII text instead of binary. Ignored for PNM_SUBTYPE_ARBITRARY_MAP.
+   */
+  bool ascii;
+} PnmOptions;
+
+/**
+ * Union used for the EncodeOptions struct
+ */
+typedef union EncodeOptionsUnion {
+  struct JpegOptions jpeg;
+  struct PngOptions png;
+  struct AvifOptions avif;
+  struct PnmOptions pnm;
+} EncodeOptionsUnion;
+
+/**
+ * Options for one of the encoders. ty represents which encoder to use and which field of val to read them from.
+ */
+typedef struct EncodeOptions {
+  enum EncodeOptionsType ty;
+  union EncodeOptionsUnion val;
+} EncodeOptions;
+
+typedef struct ExifTagsResult {
+  struct RawIterator res;
+  enum ErrorType err;
+} ExifTagsResult;
+
+/**
+ * A single EXIF tag, as returned by the iterator from dynamic_image_exif_tags.
+ *
+ * field_type is the TIFF type of the value (1 = BYTE, 2 = ASCII, 3 = SHORT, 4 = LONG, 5 = RATIONAL, 7 = UNDEFINED, ...)
+ * and count is how many values of that type there are. data points at the len bytes of the values inside the
+ * iterator's copy of the EXIF block, in the byte order given by little_endian, and stays valid until the iterator
+ * is freed. Use exif_tag_number to read numeric values without worrying about either.
+ */
+typedef struct ExifTag {
+  enum ExifIfd ifd;
+  uint16_t tag;
+  uint16_t field_type;
+  uint32_t count;
+  const uint8_t *data;
+  uintptr_t len;
+  bool little_endian;
+} ExifTag;
+
+/**
+ * A 16-bit color, for images with more precision than Rgba can hold.
+ */
+typedef struct Rgba16 {
+  uint16_t r;
+  uint16_t g;
+  uint16_t b;
+  uint16_t a;
+} Rgba16;
+
+/**
+ * Returned by dynamic_image_get_pixel16. color is transparent black if err is set.
+ */
+typedef struct Pixel16Result {
+  struct Rgba16 color;
+  enum ErrorType err;
+} Pixel16Result;
+
+/**
+ * A floating point color, usually from 0.0 to 1.0 but possibly outside of it for HDR images.
+ */
+typedef struct Rgba32F {
+  float r;
+  float g;
+  float b;
+  float a;
+} Rgba32F;
+
+/**
+ * Returned by dynamic_image_get_pixel32f. color is transparent black if err is set.
+ */
+typedef struct Pixel32FResult {
+  struct Rgba32F color;
+  enum ErrorType err;
+} Pixel32FResult;
+
+/**
+ * A borrow of the image's pixel buffer, from dynamic_image_bytes_view.
+ *
+ * Rows are row_stride bytes apart and 16-bit and f32 samples are in native byte order.
+ * data stays valid until the image is freed or changed by a function that replaces its buffer.
+ */
+typedef struct BytesView {
+  const uint8_t *data;
+  uintptr_t len;
+  uintptr_t row_stride;
+  enum ColorType color;
+} BytesView;
+
+/**
+ * Same as BytesView, but the pixels can be written through data.
+ */
+typedef struct BytesViewMut {
+  uint8_t *data;
+  uintptr_t len;
+  uintptr_t row_stride;
+  enum ColorType color;
+} BytesViewMut;
+
+/**
+ * Pixel data taken out of an image by dynamic_image_into_raw_buffer.
+ *
+ * data holds len bytes laid out like a BytesView. The buffer belongs to the caller and has to be
+ * released with rs_image_raw_buffer_free, passing the struct back unchanged.
+ */
+typedef struct RawPixelBuffer {
+  uint8_t *data;
+  uintptr_t len;
+  uintptr_t row_stride;
+  uint32_t width;
+  uint32_t height;
+  enum ColorType color;
+  /**
+   * Only meaningful to rs_image_raw_buffer_free.
+   */
+  uintptr_t capacity;
+} RawPixelBuffer;
+
+/**
+ * A single frame of an animation, as returned by the iterator from dynamic_image_frames_from_memory.
+ *
+ * image is a full RGBA8 image of the frame that has to be freed with dynamic_image_free, and
+ * left/top is where it goes on the canvas. The frame is shown for delay_numer / delay_denom milliseconds.
+ * If the frame failed to decode, err says why and image is NULL.
+ */
+typedef struct FrameResult {
+  struct DynamicImage *image;
+  uint32_t left;
+  uint32_t top;
+  uint32_t delay_numer;
+  uint32_t delay_denom;
+  enum ErrorType err;
+} FrameResult;
+
+/**
+ * Returned by the iterator from dynamic_image_pixels16.
+ */
+typedef struct PixelResult16 {
+  uint32_t x;
+  uint32_t y;
+  struct Rgba16 color;
+} PixelResult16;
+
+/**
+ * Returned by the iterator from dynamic_image_pixels32f.
+ */
+typedef struct PixelResult32F {
+  uint32_t x;
+  uint32_t y;
+  struct Rgba32F color;
+} PixelResult32F;
+
 struct LoadFromMemoryResult dynamic_image_load_from_memory(uint8_t *bytes, uintptr_t size);
 
+/**
+ * Same as dynamic_image_load_from_memory, but fails with IMAGE_ERROR_DIMENSION_ERROR or IMAGE_ERROR_INSUFFICIENT_MEMORY
+ * instead of decoding anything bigger than the given limits.
+ */
+struct LoadFromMemoryResult dynamic_image_load_from_memory_with_limits(uint8_t *bytes,
+                                                                       uintptr_t size,
+                                                                       struct DecodeLimits limits);
+
+/**
+ * Decodes an image by pulling bytes through the given RustReader instead of a buffer in memory.
+ * The format is guessed from the first few bytes, so seek_fn has to be able to go back to the start.
+ */
+struct LoadFromMemoryResult dynamic_image_load_from_reader(struct RustReader *r);
+
+/**
+ * Opens and decodes the image at the given path, guessing the format from its contents and falling back to the
+ * extension, so a file with the wrong extension or none at all still opens.
+ */
+struct LoadFromMemoryResult dynamic_image_open(const char *path);
+
+/**
+ * Opens and decodes the image at the given path as the given format, regardless of what the extension says.
+ */
+struct LoadFromMemoryResult dynamic_image_open_with_format(const char *path,
+                                                           enum ImageFormat format);
+
+/**
+ * Creates a new image with every pixel set to zero.
+ */
+struct DynamicImage *dynamic_image_new(uint32_t width, uint32_t height, enum ColorType color);
+
+/**
+ * Creates an image from a copy of the caller's pixels. 16-bit and f32 samples are read in native byte order.
+ *
+ * stride is the amount of bytes between the start of each row, or 0 if the rows are tightly packed.
+ * Fails with IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if len is too small for the given size, color type and stride.
+ */
+struct LoadFromMemoryResult dynamic_image_from_raw(uint32_t width,
+                                                   uint32_t height,
+                                                   enum ColorType color,
+                                                   const void *data,
+                                                   uintptr_t len,
+                                                   uintptr_t stride);
+
 struct DynamicImage *dynamic_image_adjust_contrast(struct DynamicImage *this_, float c);
 
-uint8_t *dynamic_image_as_bytes(struct DynamicImage *this_, uintptr_t *count);
+/**
+ * Points at the image's own pixel buffer, which stays valid until the image is freed. See also dynamic_image_bytes_view.
+ */
+uint8_t *dynamic_image_as_bytes(struct DynamicImage *this_,
+                                uintptr_t *count);
 
 struct DynamicImage *dynamic_image_blur(struct DynamicImage *this_, float sigma);
 
 struct DynamicImage *dynamic_image_brighten(struct DynamicImage *this_, int32_t value);
 
+/**
+ * Returns a deep copy of the image and its ICC profile, which has to be freed separately.
+ */
+struct DynamicImage *dynamic_image_clone(struct DynamicImage *this_);
+
 void dynamic_image_invert(struct DynamicImage *this_);
 
 enum ColorType dynamic_image_color(struct DynamicImage *this_);
@@ -277,7 +712,11 @@ uint32_t dynamic_image_height(struct DynamicImage *this_);
 
 struct DynamicImage *dynamic_image_huerotate(struct DynamicImage *this_, int32_t value);
 
-const uint8_t **dynamic_image_into_bytes(const struct DynamicImage *this_, uintptr_t *size);
+/**
+ * Same as dynamic_image_as_bytes. The pointer borrows the image's buffer, so there's nothing to free.
+ */
+const uint8_t *dynamic_image_into_bytes(const struct DynamicImage *this_,
+                                        uintptr_t *size);
 
 struct DynamicImage *dynamic_image_resize(struct DynamicImage *this_,
                                           uint32_t nwidth,
@@ -300,7 +739,7 @@ struct DynamicImage *dynamic_image_rotate270(struct DynamicImage *this_);
 
 struct DynamicImage *dynamic_image_rotate90(struct DynamicImage *this_);
 
-const char *dynamic_image_save(struct DynamicImage *this_, const char *path);
+enum ErrorType dynamic_image_save(struct DynamicImage *this_, const char *path);
 
 enum ErrorType dynamic_image_save_with_format(struct DynamicImage *this_,
                                               const char *path,
@@ -341,28 +780,88 @@ enum ErrorType dynamic_image_write_to(struct DynamicImage *this_,
 enum ErrorType dynamic_image_write_with_encoder(struct DynamicImage *this_,
                                                 struct ImageEncoder *encoder);
 
+/**
+ * Encodes the image into a buffer owned by the library, writing its length to out_len.
+ * Returns NULL on failure, in which case rs_image_last_error_code says why.
+ *
+ * The buffer has to be released with rs_image_buffer_free.
+ */
+uint8_t *dynamic_image_encode_to_buffer(struct DynamicImage *this_,
+                                        enum ImageFormat format,
+                                        uintptr_t *out_len);
+
+/**
+ * Frees a buffer returned by dynamic_image_encode_to_buffer. len has to be the length that was given back with it.
+ */
+void rs_image_buffer_free(uint8_t *buf,
+                          uintptr_t len);
+
+/**
+ * Reads the format, dimensions and color type of an image from its header, without decoding any pixels.
+ */
+struct ProbeResult dynamic_image_probe(const uint8_t *bytes,
+                                       uintptr_t size);
+
+/**
+ * Same as dynamic_image_probe, but pulls the header through a RustReader.
+ */
+struct ProbeResult dynamic_image_probe_reader(struct RustReader *r);
+
 struct Dimensions dynamic_image_dimensions(struct DynamicImage *this_);
 
 struct Rgba dynamic_image_get_pixel(struct DynamicImage *this_, uint32_t x, uint32_t y);
 
 bool dynamic_image_in_bounds(struct DynamicImage *this_, uint32_t x, uint32_t y);
 
+/**
+ * Iterates over every pixel as a PixelResult. The iterator borrows the image, so free it before the image.
+ */
 struct RawIterator dynamic_image_pixels(struct DynamicImage *this_);
 
 void dynamic_image_free(struct DynamicImage *this_);
 
 struct PixelResult ____(void);
 
+/**
+ * Returns the code of the last error reported on this thread, or IMAGE_ERROR_NONE if there hasn't been one.
+ *
+ * Like errno, this isn't cleared by calls that succeed, so only check it after a call reported an error.
+ */
+enum ErrorType rs_image_last_error_code(void);
+
+/**
+ * Returns a human readable description of the last error reported on this thread, or NULL if there hasn't been one.
+ *
+ * The string is owned by the library and stays valid until the next error happens on the same thread, so copy it if you need to keep it.
+ */
+const char *rs_image_last_error_message(void);
+
 void *iter_next(struct RawIterator *s);
 
+/**
+ * The upper bound in the result, if there is one, has to be freed with iter_usize_free.
+ */
 struct SizeHint iter_size_hint(struct RawIterator *s);
 
+/**
+ * Counts the remaining items, freeing them along the way.
+ */
 uintptr_t iter_count(struct RawIterator *s);
 
+/**
+ * Returns the last item, which is freed like any other item of the iterator. Every item before it is freed along the way.
+ */
 void *iter_last(struct RawIterator *s);
 
-void *iter_nth(struct RawIterator *s, uintptr_t n);
+/**
+ * Returns the nth item, which is freed like any other item of the iterator. The items skipped over are freed along the way.
+ */
+void *iter_nth(struct RawIterator *s,
+               uintptr_t n);
 
+/**
+ * The items stepped over are freed as they're passed.
+ */
 struct RawIterator iter_step_by(struct RawIterator *s, uintptr_t step);
 
 struct RawIterator iter_chain(struct RawIterator *s, struct RawIterator other);
@@ -373,6 +872,9 @@ struct RawIterator iter_map(struct RawIterator *s, void (*f)(void*));
 
 void iter_for_each(struct RawIterator *s, void (*f)(void*));
 
+/**
+ * The items predicate rejects are freed as they're passed.
+ */
 struct RawIterator iter_filter(struct RawIterator *s, bool (*predicate)(void*));
 
 struct RawIterator iter_filter_map(struct RawIterator *s, struct RawIterator *(*f)(void*));
@@ -381,12 +883,21 @@ struct RawIterator iter_enumerate(struct RawIterator *s);
 
 struct RawIterator iter_peekable(struct RawIterator *s);
 
+/**
+ * The items skipped are freed as they're passed.
+ */
 struct RawIterator iter_skip_while(struct RawIterator *s, bool (*predicate)(void*));
 
+/**
+ * The item that ends it is freed.
+ */
 struct RawIterator iter_take_while(struct RawIterator *s, bool (*predicate)(void*));
 
 struct RawIterator iter_map_while(struct RawIterator *s, struct RawIterator *(*predicate)(void*));
 
+/**
+ * The items skipped are freed as they're passed.
+ */
 struct RawIterator iter_skip(struct RawIterator *s, uintptr_t n);
 
 struct RawIterator iter_take(struct RawIterator *s, uintptr_t n);
@@ -401,28 +912,63 @@ struct RawIterator iter_inspect(struct RawIterator *s, void (*f)(void*));
 
 struct RawIterator iter_by_ref(struct RawIterator *s);
 
+/**
+ * Collects the remaining items into an array, writing its length to size.
+ *
+ * The array has to be freed with iter_collect_free, and the items in it with iter_item_free.
+ */
 void **iter_collect(struct RawIterator *s, uintptr_t *size);
 
+/**
+ * Frees an array returned by iter_collect, but not the items in it. size has to be the length that was given back with it.
+ */
+void iter_collect_free(void **items,
+                       uintptr_t size);
+
 void *iter_fold(struct RawIterator *s, void *init, void *(*f)(void*, void*));
 
 void *iter_reduce(struct RawIterator *s, void *(*f)(void*, void*));
 
+/**
+ * Every item f is called with is freed afterwards.
+ */
 bool iter_all(struct RawIterator *s, bool (*f)(void*));
 
+/**
+ * Every item f is called with is freed afterwards.
+ */
 bool iter_any(struct RawIterator *s, bool (*f)(void*));
 
-void *iter_find(struct RawIterator *s, bool (*predicate)(void*));
+/**
+ * Returns the first item predicate accepts, which is freed like any other item of the iterator. The items it
+ * rejects are freed along the way.
+ */
+void *iter_find(struct RawIterator *s,
+                bool (*predicate)(void*));
 
 void *iter_find_map(struct RawIterator *s, void *(*f)(void*));
 
-uintptr_t *iter_position(struct RawIterator *s, bool (*predicate)(void*));
+/**
+ * The result has to be freed with iter_usize_free. Every item predicate is called with is freed afterwards.
+ */
+uintptr_t *iter_position(struct RawIterator *s,
+                         bool (*predicate)(void*));
 
+/**
+ * Returns the item with the highest address, which is freed like any other item of the iterator. The rest are freed.
+ */
 void *iter_max(struct RawIterator *s);
 
+/**
+ * Returns the item with the lowest address, which is freed like any other item of the iterator. The rest are freed.
+ */
 void *iter_min(struct RawIterator *s);
 
 enum Ordering iter_cmp(struct RawIterator *s, struct RawIterator other);
 
+/**
+ * The result has to be freed with iter_ordering_free.
+ */
 enum Ordering *iter_partial_cmp(struct RawIterator *s, struct RawIterator other);
 
 bool iter_eq(struct RawIterator *s, struct RawIterator other);
@@ -437,4 +983,453 @@ bool iter_gt(struct RawIterator *s, struct RawIterator other);
 
 bool iter_ge(struct RawIterator *s, struct RawIterator other);
 
+/**
+ * Frees the iterator. Doing this to an iterator twice, or to a copy of one that's already been freed, is undefined.
+ *
+ * Items that came out of it have to be freed with iter_item_free first.
+ */
+void iter_free(struct RawIterator *s);
+
+/**
+ * Frees an item returned by iter_next, or by anything else that hands out the iterator's items. Does nothing if item is NULL.
+ *
+ * s has to be the iterator the item came from, and it has to not have been freed yet.
+ */
+void iter_item_free(struct RawIterator *s,
+                    void *item);
+
+/**
+ * Frees the number returned by iter_position or in the upper bound of iter_size_hint.
+ */
+void iter_usize_free(uintptr_t *n);
+
+/**
+ * Frees the ordering returned by iter_partial_cmp.
+ */
+void iter_ordering_free(enum Ordering *o);
+
+/**
+ * Multiplies the color channels of every pixel by its alpha, in place, which is what GPU compositors expect.
+ * Images without an alpha channel are left untouched.
+ *
+ * Nothing marks the image as premultiplied afterwards, so the other functions keep treating its alpha as
+ * straight. Undo it with dynamic_image_unpremultiply before handing the image to them.
+ */
+void dynamic_image_premultiply(struct DynamicImage *this_);
+
+/**
+ * Divides the color channels of every pixel by its alpha, in place, turning premultiplied colors back into
+ * straight ones. Fully transparent pixels become transparent black.
+ *
+ * For 8 and 16-bit images this can't bring back the precision premultiplying lost at low alpha.
+ */
+void dynamic_image_unpremultiply(struct DynamicImage *this_);
+
+/**
+ * Same as dynamic_image_resize_with_color_space, but resamples with premultiplied alpha,
+ * which keeps the color of transparent pixels from showing up as dark fringes along the edges.
+ */
+struct DynamicImage *dynamic_image_resize_premultiplied(struct DynamicImage *this_,
+                                                        uint32_t nwidth,
+                                                        uint32_t nheight,
+                                                        enum FilterType filter,
+                                                        enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_resize_exact_with_color_space, but resamples with premultiplied alpha.
+ */
+struct DynamicImage *dynamic_image_resize_exact_premultiplied(struct DynamicImage *this_,
+                                                              uint32_t nwidth,
+                                                              uint32_t nheight,
+                                                              enum FilterType filter,
+                                                              enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_blur_with_color_space, but blurs with premultiplied alpha.
+ */
+struct DynamicImage *dynamic_image_blur_premultiplied(struct DynamicImage *this_,
+                                                      float sigma,
+                                                      enum ColorSpaceMode mode);
+
+/**
+ * Decodes every frame of an animated GIF, APNG or WebP, returning an iterator of FrameResult pointers.
+ *
+ * The bytes are copied, so they can be freed once this returns. If a frame fails to decode, the iterator yields a
+ * FrameResult with err set and a NULL image in its place, then ends. A PNG without an animation yields no frames.
+ */
+struct FramesResult dynamic_image_frames_from_memory(const uint8_t *bytes,
+                                                     uintptr_t size,
+                                                     enum ImageFormat format);
+
+/**
+ * Starts a GIF that gets written through the given RustWriter. The RustWriter is copied, so only its user_data has to stay alive.
+ *
+ * The canvas takes the size of the first frame. The handle is freed by gif_encoder_finish, or by gif_encoder_free to give up on the GIF.
+ */
+struct GifEncoder *gif_encoder_new(struct RustWriter *w);
+
+/**
+ * Sets how many times the animation repeats, where 0 means that it loops forever.
+ */
+enum ErrorType gif_encoder_set_repeat(struct GifEncoder *enc, uint16_t count);
+
+/**
+ * Appends the image as the next frame, shown for delay_ms milliseconds. The image is converted to RGBA8 and left untouched.
+ *
+ * GIF delays are in hundredths of a second, so delay_ms is rounded to the nearest multiple of 10. Anything above 0
+ * stays at least 10, since many viewers treat a delay of 0 as "as fast as possible".
+ */
+enum ErrorType gif_encoder_add_frame(struct GifEncoder *enc,
+                                     struct DynamicImage *img,
+                                     uint32_t delay_ms);
+
+/**
+ * Writes the end of the GIF, flushes the writer and frees the handle.
+ *
+ * If no frame was added, nothing is written at all. The handle is freed even if writing fails.
+ */
+enum ErrorType gif_encoder_finish(struct GifEncoder *enc);
+
+/**
+ * Frees the handle without finishing the GIF, so nothing more is written to the writer. Does nothing if enc is NULL.
+ *
+ * Whatever was already written stays written, which leaves the writer with a GIF that has no end.
+ */
+void gif_encoder_free(struct GifEncoder *enc);
+
+/**
+ * Draws top over bottom with its top left corner at (x, y), blending by top's alpha.
+ * The offsets can be negative or past the edge, in which case only the part that overlaps is drawn.
+ */
+void dynamic_image_overlay(struct DynamicImage *bottom,
+                           struct DynamicImage *top,
+                           int64_t x,
+                           int64_t y);
+
+/**
+ * Same as dynamic_image_overlay, but copies top's pixels over bottom's without blending.
+ */
+void dynamic_image_replace(struct DynamicImage *bottom,
+                           struct DynamicImage *top,
+                           int64_t x,
+                           int64_t y);
+
+/**
+ * Repeats top across the whole of bottom, starting from the top left corner.
+ */
+void dynamic_image_tile(struct DynamicImage *bottom, struct DynamicImage *top);
+
+/**
+ * Draws top over bottom with its top left corner at (x, y), combining colors with the given blend mode.
+ *
+ * opacity scales top's alpha and is clamped to 0..1. The blending is done in f32, so 16-bit and f32 images
+ * keep their precision. Like dynamic_image_overlay, the offsets can be negative or past the edge.
+ */
+void dynamic_image_blend(struct DynamicImage *bottom,
+                         struct DynamicImage *top,
+                         int64_t x,
+                         int64_t y,
+                         enum BlendMode mode,
+                         float opacity);
+
+/**
+ * Same as dynamic_image_write_to, but with control over the encoder's settings. The format is taken from options.ty.
+ */
+enum ErrorType dynamic_image_write_to_with_options(struct DynamicImage *this_,
+                                                   struct RustWriter *w,
+                                                   struct EncodeOptions options);
+
+/**
+ * Same as dynamic_image_load_from_memory, but rotates and flips the image the way its EXIF Orientation tag says to,
+ * so that it comes out the right way up. If the EXIF data can't be read, the image is left the way it was stored.
+ */
+struct LoadFromMemoryResult dynamic_image_load_from_memory_oriented(const uint8_t *bytes,
+                                                                    uintptr_t size);
+
+/**
+ * Copies the raw EXIF block out of an encoded image into a buffer that has to be released with rs_image_buffer_free.
+ *
+ * The block starts with the TIFF header, so it can be handed to any EXIF parser. If the image has no EXIF data, or
+ * its format doesn't carry any, *out is set to NULL and *out_len to 0 and IMAGE_ERROR_NONE is returned.
+ */
+enum ErrorType dynamic_image_exif_bytes(const uint8_t *bytes,
+                                        uintptr_t size,
+                                        uint8_t **out,
+                                        uintptr_t *out_len);
+
+/**
+ * Parses the EXIF data of an encoded image, returning an iterator of ExifTag pointers.
+ *
+ * The bytes are copied, so they can be freed once this returns. An image without EXIF data gives an empty iterator,
+ * while EXIF data that isn't valid TIFF gives IMAGE_ERROR_DECODING.
+ */
+struct ExifTagsResult dynamic_image_exif_tags(const uint8_t *bytes,
+                                              uintptr_t size);
+
+/**
+ * Reads the value at index of a numeric tag as a double, dividing rationals out.
+ * Returns NaN for ASCII and UNDEFINED tags, or if index is past count.
+ */
+double exif_tag_number(const struct ExifTag *tag, uint32_t index);
+
+/**
+ * Points at the ICC profile attached to the image and writes its length to out_len, or returns NULL if it has none.
+ *
+ * Images loaded from a file that embeds a profile keep it, and it's written back out when encoding to PNG, JPEG,
+ * WebP or TIFF. Profiles whose color space doesn't match the decoded pixels, like the CMYK profile of a CMYK JPEG
+ * that decodes to RGB, are dropped on load. The pointer stays valid until the profile is replaced or the image is
+ * freed. Functions that return a new image don't carry the profile over, with the exception of dynamic_image_clone.
+ */
+const uint8_t *dynamic_image_icc_profile(struct DynamicImage *this_,
+                                         uintptr_t *out_len);
+
+/**
+ * Attaches a copy of the given ICC profile to the image, replacing any it already had.
+ * Passing NULL or a length of 0 removes the profile.
+ *
+ * The profile isn't checked or applied to the pixels, it's only written out alongside them, and left out if its
+ * color space doesn't match the image's color type.
+ */
+void dynamic_image_set_icc_profile(struct DynamicImage *this_,
+                                   const uint8_t *icc,
+                                   uintptr_t len);
+
+/**
+ * Converts the pixels of the image from the src ICC profile to the dst one, and attaches dst to the image.
+ *
+ * If src is NULL the image's own profile is used, and if dst is NULL the image is converted to sRGB and left
+ * without a profile. An image with neither is taken to be sRGB. Only matrix/TRC (matrix-shaper) RGB and gray
+ * profiles are supported; CMYK, Lab and LUT based profiles give IMAGE_ERROR_UNSUPPORTED_COLOR and leave the
+ * image untouched, as do a gray dst for a color image and an RGB dst for a gray one. The conversion is done
+ * in f32, so 16-bit and f32 images keep their precision, but values are clamped to the gamut of dst.
+ */
+enum ErrorType dynamic_image_convert_profile(struct DynamicImage *this_,
+                                             const uint8_t *src_icc,
+                                             uintptr_t src_len,
+                                             const uint8_t *dst_icc,
+                                             uintptr_t dst_len,
+                                             enum RenderingIntent intent);
+
+/**
+ * Same as dynamic_image_resize, but resamples in the given color space.
+ */
+struct DynamicImage *dynamic_image_resize_with_color_space(struct DynamicImage *this_,
+                                                           uint32_t nwidth,
+                                                           uint32_t nheight,
+                                                           enum FilterType filter,
+                                                           enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_resize_exact, but resamples in the given color space.
+ */
+struct DynamicImage *dynamic_image_resize_exact_with_color_space(struct DynamicImage *this_,
+                                                                 uint32_t nwidth,
+                                                                 uint32_t nheight,
+                                                                 enum FilterType filter,
+                                                                 enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_resize_to_fill, but resamples in the given color space.
+ */
+struct DynamicImage *dynamic_image_resize_to_fill_with_color_space(struct DynamicImage *this_,
+                                                                   uint32_t nwidth,
+                                                                   uint32_t nheight,
+                                                                   enum FilterType filter,
+                                                                   enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_thumbnail, but averages in the given color space.
+ */
+struct DynamicImage *dynamic_image_thumbnail_with_color_space(struct DynamicImage *this_,
+                                                              uint32_t nwidth,
+                                                              uint32_t nheight,
+                                                              enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_thumbnail_exact, but averages in the given color space.
+ */
+struct DynamicImage *dynamic_image_thumbnail_exact_with_color_space(struct DynamicImage *this_,
+                                                                    uint32_t nwidth,
+                                                                    uint32_t nheight,
+                                                                    enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_blur, but blurs in the given color space.
+ */
+struct DynamicImage *dynamic_image_blur_with_color_space(struct DynamicImage *this_,
+                                                         float sigma,
+                                                         enum ColorSpaceMode mode);
+
+/**
+ * Same as dynamic_image_unsharpen, but sharpens in the given color space.
+ *
+ * threshold is still on a 0-255 scale, compared against the difference between the image and its blurred copy in
+ * the chosen color space. In linear mode the sharpening is done in f32 and alpha is left as it is.
+ */
+struct DynamicImage *dynamic_image_unsharpen_with_color_space(struct DynamicImage *this_,
+                                                              float sigma,
+                                                              int32_t threshold,
+                                                              enum ColorSpaceMode mode);
+
+/**
+ * Copies all of src into dst with its top left corner at (x, y), converting it to dst's color type.
+ * Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if src doesn't fit, in which case dst is left alone.
+ */
+enum ErrorType dynamic_image_copy_from(struct DynamicImage *dst,
+                                       struct DynamicImage *src,
+                                       uint32_t x,
+                                       uint32_t y);
+
+/**
+ * Sets the pixel at (x, y), converting the color to the image's color type.
+ * Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the pixel is out of bounds.
+ */
+enum ErrorType dynamic_image_put_pixel(struct DynamicImage *this_,
+                                       uint32_t x,
+                                       uint32_t y,
+                                       struct Rgba color);
+
+/**
+ * Sets every pixel of the image to the given color.
+ */
+void dynamic_image_fill(struct DynamicImage *this_, struct Rgba color);
+
+/**
+ * Copies a width x height block of the caller's pixels into the image at (x, y), converting them to the image's color type.
+ *
+ * src is read in native byte order with stride bytes between the start of each row, or tightly packed if stride is 0.
+ * Only the 8-bit, 16-bit and f32 color types plus BGR8 and BGRA8 are supported as a source.
+ * Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the region doesn't fit in the image or stride is shorter than a
+ * row, and IMAGE_ERROR_PARAMETER_MALFORMED if src is NULL.
+ */
+enum ErrorType dynamic_image_write_region(struct DynamicImage *this_,
+                                          uint32_t x,
+                                          uint32_t y,
+                                          uint32_t width,
+                                          uint32_t height,
+                                          const void *src,
+                                          uintptr_t stride,
+                                          enum ExtendedColorType color);
+
+/**
+ * Same as dynamic_image_get_pixel, but without losing precision on 16-bit and f32 images.
+ * Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the pixel is out of bounds.
+ */
+struct Pixel16Result dynamic_image_get_pixel16(struct DynamicImage *this_, uint32_t x, uint32_t y);
+
+/**
+ * Same as dynamic_image_get_pixel, but without losing precision on 16-bit and f32 images.
+ * Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the pixel is out of bounds.
+ */
+struct Pixel32FResult dynamic_image_get_pixel32f(struct DynamicImage *this_,
+                                                 uint32_t x,
+                                                 uint32_t y);
+
+/**
+ * Same as dynamic_image_put_pixel, but without losing precision on 16-bit and f32 images.
+ */
+enum ErrorType dynamic_image_put_pixel16(struct DynamicImage *this_,
+                                         uint32_t x,
+                                         uint32_t y,
+                                         struct Rgba16 color);
+
+/**
+ * Same as dynamic_image_put_pixel, but without losing precision on 16-bit and f32 images.
+ */
+enum ErrorType dynamic_image_put_pixel32f(struct DynamicImage *this_,
+                                          uint32_t x,
+                                          uint32_t y,
+                                          struct Rgba32F color);
+
+/**
+ * Iterates over every pixel as a PixelResult16. The pixels are converted up front, so later changes to the image aren't seen.
+ */
+struct RawIterator dynamic_image_pixels16(struct DynamicImage *this_);
+
+/**
+ * Iterates over every pixel as a PixelResult32F. The pixels are converted up front, so later changes to the image aren't seen.
+ */
+struct RawIterator dynamic_image_pixels32f(struct DynamicImage *this_);
+
+/**
+ * Borrows the image's pixels without copying them.
+ */
+struct BytesView dynamic_image_bytes_view(struct DynamicImage *this_);
+
+/**
+ * Borrows the image's pixels without copying them, so that they can be edited in place.
+ */
+struct BytesViewMut dynamic_image_bytes_view_mut(struct DynamicImage *this_);
+
+/**
+ * Consumes the image and hands its pixel data to the caller without copying it.
+ *
+ * The handle is freed and mustn't be used afterwards, even if this fails.
+ */
+struct RawPixelBuffer dynamic_image_into_raw_buffer(struct DynamicImage *this_);
+
+/**
+ * Frees the pixel data returned by dynamic_image_into_raw_buffer. Does nothing if data is NULL.
+ */
+void rs_image_raw_buffer_free(struct RawPixelBuffer buf);
+
+/**
+ * Rotates the image clockwise by degrees around its center, filling the uncovered corners with bg.
+ *
+ * If expand_canvas is true the result is made big enough to hold the whole rotated image, otherwise it
+ * keeps the original size and the corners are cut off.
+ */
+struct DynamicImage *dynamic_image_rotate(struct DynamicImage *this_,
+                                          float degrees,
+                                          enum FilterType filter,
+                                          struct Rgba bg,
+                                          bool expand_canvas);
+
+/**
+ * Same as dynamic_image_rotate, but interpolates with premultiplied alpha, which keeps the color of
+ * transparent pixels from showing up as dark fringes along the edges.
+ */
+struct DynamicImage *dynamic_image_rotate_premultiplied(struct DynamicImage *this_,
+                                                        float degrees,
+                                                        enum FilterType filter,
+                                                        struct Rgba bg,
+                                                        bool expand_canvas);
+
+/**
+ * Applies the affine transform matrix to the image, rendering the result into an out_w x out_h image.
+ *
+ * matrix is the top two rows of a 3x3 matrix, {a, b, c, d, e, f}, mapping a point in the source to
+ * (a*x + b*y + c, d*x + e*y + f) in the result. Parts of the result that don't come from the source are
+ * transparent black. Returns NULL with IMAGE_ERROR_PARAMETER_MALFORMED if the matrix can't be inverted.
+ */
+struct DynamicImage *dynamic_image_affine(struct DynamicImage *this_,
+                                          const float *matrix,
+                                          uint32_t out_w,
+                                          uint32_t out_h,
+                                          enum FilterType filter);
+
+/**
+ * Maps the quadrilateral src_quad of the image onto an out_width x out_height rectangle, like when straightening
+ * a photographed page. Anything outside the source is transparent black.
+ *
+ * src_quad holds the x and y of the corners that end up at the top left, top right, bottom right and bottom left,
+ * in that order, with (0, 0) being the top left corner of the image. Returns NULL with
+ * IMAGE_ERROR_PARAMETER_MALFORMED if three of the corners are on one line.
+ */
+struct DynamicImage *dynamic_image_warp_perspective(struct DynamicImage *this_,
+                                                    const float *src_quad,
+                                                    uint32_t out_width,
+                                                    uint32_t out_height,
+                                                    enum FilterType filter);
+
+/**
+ * Same as dynamic_image_warp_perspective, but fills the parts outside the source with fill.
+ */
+struct DynamicImage *dynamic_image_warp_perspective_with_fill(struct DynamicImage *this_,
+                                                              const float *src_quad,
+                                                              uint32_t out_width,
+                                                              uint32_t out_height,
+                                                              enum FilterType filter,
+                                                              struct Rgba fill);
+
 #endif  /* __INTERNAL_IMAGE_LOAD_H */
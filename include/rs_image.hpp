@@ -3,6 +3,9 @@
 #include <cstdlib>
 #include <exception>
 #include <functional>
+#include <optional>
+#include <string>
+#include <vector>
 
 namespace rs_image {
 namespace internal {
@@ -12,27 +15,65 @@ using std::abs;
 }
 }  // namespace internal
 
+using internal::BlendMode;
+using internal::BytesView;
+using internal::BytesViewMut;
+using internal::ColorSpaceMode;
 using internal::ColorType;
+using internal::DecodeLimits;
+using internal::ExifIfd;
+using internal::ExifTag;
 using internal::Dimensions;
+using internal::FilterType;
+using internal::ImageFormat;
 using internal::Ordering;
 using internal::PixelResult;
+using internal::PixelResult16;
+using internal::PixelResult32F;
+using internal::ProbeResult;
+using internal::RawPixelBuffer;
+using internal::RenderingIntent;
 using internal::Rgba;
+using internal::Rgba16;
+using internal::Rgba32F;
+using internal::RustReader;
+using internal::RustWriter;
 using internal::SizeHint;
+using internal::exif_tag_number;
+using internal::rs_image_raw_buffer_free;
 
 class image_error : public std::exception {
   internal::ErrorType er;
+  std::string message;
 
  public:
-  image_error(internal::ErrorType er) : er(er) {};
+  image_error(internal::ErrorType er) : er(er) {
+    auto msg = internal::rs_image_last_error_message();
+    if (msg != NULL) {
+      message = msg;
+    }
+  };
   const char* what() const noexcept override;
+  // The full description of the error as given by the image crate, which is
+  // more specific then what().
+  const char* detail() const noexcept { return message.c_str(); };
 };
 
 template <class T>
 class Iterator {
   internal::RawIterator* inner;
 
+  // Copies an item out and frees it, or gives back nothing if the iterator
+  // ran out.
+  std::optional<T> take(void* item);
+
  public:
+  // Takes over inner, which has to have been allocated with new.
   Iterator(internal::RawIterator* inner) : inner(inner) {};
+  Iterator(const Iterator&) = delete;
+  Iterator& operator=(const Iterator&) = delete;
+  Iterator(Iterator&& other) : inner(other.inner) { other.inner = nullptr; };
+  ~Iterator();
 
   SizeHint size_hint();
   uintptr_t count();
@@ -64,12 +105,12 @@ class Iterator {
   bool le(Iterator other);
   bool gt(Iterator other);
   bool ge(Iterator other);
-  T next();
-  T last();
-  T nth(uintptr_t n);
+  std::optional<T> next();
+  std::optional<T> last();
+  std::optional<T> nth(uintptr_t n);
   T fold(T init, T (*)(T, T));
   T reduce(T (*)(T, T));
-  T find(bool (*)(T*));
+  std::optional<T> find(bool (*)(T*));
   T find_map(T (*)(T));
   size_t* position(bool (*)(T*));
   T max();
@@ -83,22 +124,124 @@ class Iterator {
   T* min_by(Ordering (*)(void*, void*));*/
 };
 
+class DynamicImage;
+
+// A single frame of an animation, as returned by DynamicImage::frames.
+struct Frame {
+  // A full RGBA8 image of the frame, owned by the caller.
+  DynamicImage* image;
+  // Where the frame goes on the canvas.
+  uint32_t left;
+  uint32_t top;
+  // The frame is shown for delay_numer / delay_denom milliseconds.
+  uint32_t delay_numer;
+  uint32_t delay_denom;
+};
+
 class DynamicImage {
  private:
   internal::DynamicImage* img;
   DynamicImage(internal::DynamicImage* img) : img(img) {};
+  friend class GifEncoder;
 
  public:
   uint32_t width();
 
   DynamicImage(std::vector<char> data);
+  DynamicImage(uint32_t width, uint32_t height, ColorType color);
   ~DynamicImage();
 
+  static DynamicImage* open(const char* path);
+  // Loads the image and turns it the right way up according to its EXIF
+  // Orientation tag.
+  static DynamicImage* load_oriented(const std::vector<char>& data);
+  // Throws instead of decoding anything bigger than the given limits.
+  static DynamicImage* load_with_limits(const std::vector<char>& data,
+                                        DecodeLimits limits);
+  static DynamicImage* open_with_format(const char* path, ImageFormat format);
+  // Copies the raw EXIF block, starting with its TIFF header, out of an
+  // encoded image. Empty if the image has no EXIF data.
+  static std::vector<uint8_t> exif_bytes(const std::vector<char>& data);
+  // Parses the EXIF data of an encoded image. The data each ExifTag points at
+  // stays valid for as long as the iterator does.
+  static Iterator<ExifTag> exif_tags(const std::vector<char>& data);
+  // Reads the format, dimensions and color type from the image's header
+  // without decoding any pixels. Throws instead of returning a failed probe,
+  // so err is always IMAGE_ERROR_NONE.
+  static ProbeResult probe(const std::vector<char>& data);
+  static ProbeResult probe(RustReader* r);
+  // Decodes every frame of an animated GIF, APNG or WebP. If any frame fails
+  // to decode, the frames decoded so far are freed and the error is thrown.
+  static std::vector<Frame> frames(const std::vector<char>& data,
+                                   ImageFormat format);
+  static DynamicImage* from_raw(uint32_t width,
+                                uint32_t height,
+                                ColorType color,
+                                const void* data,
+                                size_t len,
+                                size_t stride = 0);
+
   // DynamicImage* from_decoder();
   uint8_t* as_bytes(size_t* count);
+  BytesView bytes_view();
+  BytesViewMut bytes_view_mut();
+  // Hands the pixel data over without copying it, leaving this image empty so
+  // that it can only be destroyed. The buffer has to be released with
+  // rs_image_raw_buffer_free.
+  RawPixelBuffer into_raw_buffer();
+  DynamicImage* resize(uint32_t nwidth, uint32_t nheight, FilterType filter);
+  DynamicImage* resize(uint32_t nwidth,
+                       uint32_t nheight,
+                       FilterType filter,
+                       ColorSpaceMode mode);
+  DynamicImage* resize_exact(uint32_t nwidth,
+                             uint32_t nheight,
+                             FilterType filter);
+  DynamicImage* resize_exact(uint32_t nwidth,
+                             uint32_t nheight,
+                             FilterType filter,
+                             ColorSpaceMode mode);
+  DynamicImage* resize_to_fill(uint32_t nwidth,
+                               uint32_t nheight,
+                               FilterType filter);
+  DynamicImage* resize_to_fill(uint32_t nwidth,
+                               uint32_t nheight,
+                               FilterType filter,
+                               ColorSpaceMode mode);
+  DynamicImage* thumbnail(uint32_t nwidth, uint32_t nheight);
+  DynamicImage* thumbnail(uint32_t nwidth,
+                          uint32_t nheight,
+                          ColorSpaceMode mode);
+  DynamicImage* thumbnail_exact(uint32_t nwidth, uint32_t nheight);
+  DynamicImage* thumbnail_exact(uint32_t nwidth,
+                                uint32_t nheight,
+                                ColorSpaceMode mode);
   DynamicImage* blur(float sigma);
+  DynamicImage* blur(float sigma, ColorSpaceMode mode);
+  DynamicImage* unsharpen(float sigma, int32_t threshold);
+  DynamicImage* unsharpen(float sigma,
+                          int32_t threshold,
+                          ColorSpaceMode mode);
+  // Same as resize, resize_exact, blur and rotate, but with premultiplied
+  // alpha, which keeps the color of transparent pixels from bleeding into
+  // their neighbours.
+  DynamicImage* resize_premultiplied(uint32_t nwidth,
+                                     uint32_t nheight,
+                                     FilterType filter,
+                                     ColorSpaceMode mode);
+  DynamicImage* resize_exact_premultiplied(uint32_t nwidth,
+                                           uint32_t nheight,
+                                           FilterType filter,
+                                           ColorSpaceMode mode);
+  DynamicImage* blur_premultiplied(float sigma, ColorSpaceMode mode);
+  DynamicImage* rotate_premultiplied(float degrees,
+                                     FilterType filter,
+                                     Rgba background,
+                                     bool expand_canvas);
   DynamicImage* brighten(float value);
   void invert();
+  void premultiply();
+  void unpremultiply();
   ColorType color();
   DynamicImage* fliph();
   DynamicImage* flipv();
@@ -107,6 +250,19 @@ class DynamicImage {
   DynamicImage* rotate180();
   DynamicImage* rotate270();
   DynamicImage* rotate90();
+  DynamicImage* rotate(float degrees,
+                       FilterType filter,
+                       Rgba background,
+                       bool expand_canvas);
+  DynamicImage* affine(const float matrix[6],
+                       uint32_t out_width,
+                       uint32_t out_height,
+                       FilterType filter);
+  DynamicImage* warp_perspective(const float src_quad[8],
+                                 uint32_t out_width,
+                                 uint32_t out_height,
+                                 FilterType filter,
+                                 Rgba fill = {0, 0, 0, 0});
   DynamicImage* into_luma16();
   DynamicImage* into_luma8();
   DynamicImage* into_luma_alpha16();
@@ -119,8 +275,56 @@ class DynamicImage {
   DynamicImage* into_rgba8();
   Dimensions get_dimensions();
   Rgba get_pixel(uint32_t x, uint32_t y);
+  void put_pixel(uint32_t x, uint32_t y, Rgba color);
+  // Same as get_pixel and put_pixel, but without losing precision on 16-bit
+  // and f32 images.
+  Rgba16 get_pixel16(uint32_t x, uint32_t y);
+  Rgba32F get_pixel32f(uint32_t x, uint32_t y);
+  void put_pixel16(uint32_t x, uint32_t y, Rgba16 color);
+  void put_pixel32f(uint32_t x, uint32_t y, Rgba32F color);
+  void fill(Rgba color);
+  DynamicImage* clone();
+  std::vector<uint8_t> icc_profile();
+  void set_icc_profile(const std::vector<uint8_t>& icc);
+  void convert_profile(const std::vector<uint8_t>& src_icc,
+                       const std::vector<uint8_t>& dst_icc,
+                       RenderingIntent intent);
+  void copy_from(DynamicImage& src, uint32_t x, uint32_t y);
+  void overlay(DynamicImage& top, int64_t x, int64_t y);
+  void replace(DynamicImage& top, int64_t x, int64_t y);
+  void tile(DynamicImage& top);
+  void blend(DynamicImage& top,
+             int64_t x,
+             int64_t y,
+             BlendMode mode,
+             float opacity = 1.0);
   bool in_bounds(uint32_t x, uint32_t y);
-  Iterator<PixelResult*> pixels();
+  Iterator<PixelResult> pixels();
+  Iterator<PixelResult16> pixels16();
+  Iterator<PixelResult32F> pixels32f();
+  std::vector<uint8_t> encode(ImageFormat format);
+};
+
+// Writes an animated GIF one frame at a time through a RustWriter, which is
+// copied, so only its user_data has to stay alive. Destroying the encoder
+// without calling finish gives up on the GIF, leaving it without an end.
+class GifEncoder {
+  internal::GifEncoder* enc;
+
+ public:
+  GifEncoder(RustWriter* w);
+  GifEncoder(const GifEncoder&) = delete;
+  GifEncoder& operator=(const GifEncoder&) = delete;
+  ~GifEncoder();
+
+  // How many times the animation repeats, where 0 means that it loops
+  // forever.
+  void set_repeat(uint16_t count);
+  // The delay is rounded to the nearest hundredth of a second.
+  void add_frame(DynamicImage& img, uint32_t delay_ms);
+  // Writes the end of the GIF and flushes the writer. Nothing can be added
+  // afterwards.
+  void finish();
 };
 
 // Thank you C++ gods for appearently making it so C++ templates need to be
@@ -250,16 +454,32 @@ bool Iterator<T>::ge(Iterator other) {
   return *(Iterator<T>*)internal::iter_ge(this->inner, other);
 }
 template <class T>
-T Iterator<T>::next() {
-  return *(T*)internal::iter_next(this->inner);
+Iterator<T>::~Iterator() {
+  if (this->inner != nullptr) {
+    internal::iter_free(this->inner);
+    delete this->inner;
+  }
+}
+template <class T>
+std::optional<T> Iterator<T>::take(void* item) {
+  if (item == nullptr) {
+    return std::nullopt;
+  }
+  T copy = *(T*)item;
+  internal::iter_item_free(this->inner, item);
+  return copy;
+}
+template <class T>
+std::optional<T> Iterator<T>::next() {
+  return this->take(internal::iter_next(this->inner));
 }
 template <class T>
-T Iterator<T>::last() {
-  return *(T*)internal::iter_last(this->inner);
+std::optional<T> Iterator<T>::last() {
+  return this->take(internal::iter_last(this->inner));
 }
 template <class T>
-T Iterator<T>::nth(uintptr_t n) {
-  return *(T*)internal::iter_nth(this->inner, n);
+std::optional<T> Iterator<T>::nth(uintptr_t n) {
+  return this->take(internal::iter_nth(this->inner, n));
 }
 template <class T>
 T Iterator<T>::fold(T init, T (*f)(T, T)) {
@@ -270,8 +490,8 @@ T Iterator<T>::reduce(T (*f)(T, T)) {
   return *(T*)internal::iter_reduce(this->inner, f);
 }
 template <class T>
-T Iterator<T>::find(bool (*f)(T*)) {
-  return *(T*)internal::iter_find(this->inner, f);
+std::optional<T> Iterator<T>::find(bool (*f)(T*)) {
+  return this->take(internal::iter_find(this->inner, (bool (*)(void*))f));
 }
 template <class T>
 T Iterator<T>::find_map(T (*f)(T)) {
@@ -296,8 +516,15 @@ Ordering* Iterator<T>::partial_cmp(Iterator* other) {
 template <class T>
 std::vector<T> Iterator<T>::collect() {
   uintptr_t size;
-  auto bytes = internal::iter_collect(this->inner, &size);
-  return std::vector(bytes, bytes + size);
+  auto items = internal::iter_collect(this->inner, &size);
+  std::vector<T> out;
+  out.reserve(size);
+  for (uintptr_t i = 0; i < size; i++) {
+    out.push_back(*(T*)items[i]);
+    internal::iter_item_free(this->inner, items[i]);
+  }
+  internal::iter_collect_free(items, size);
+  return out;
 }
 #endif
 
@@ -0,0 +1,323 @@
+use std::{
+    ffi::c_void,
+    io::{Cursor, Write},
+};
+
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, Frames,
+};
+
+use crate::{
+    error::set_last_error,
+    ffi_guard, get_image_error,
+    guard::PanicFallback,
+    iter::{make_raw_iterator, RawIterator},
+    thin::{free_boxed, ThinIterator},
+    DynamicImage, ErrorType, ImageFormat, RustWriter,
+};
+
+/// A single frame of an animation, as returned by the iterator from dynamic_image_frames_from_memory.
+///
+/// image is a full RGBA8 image of the frame that has to be freed with dynamic_image_free, and
+/// left/top is where it goes on the canvas. The frame is shown for delay_numer / delay_denom milliseconds.
+/// If the frame failed to decode, err says why and image is NULL.
+#[repr(C)]
+pub struct FrameResult {
+    pub image: *mut DynamicImage,
+    pub left: u32,
+    pub top: u32,
+    pub delay_numer: u32,
+    pub delay_denom: u32,
+    pub err: ErrorType,
+}
+
+#[repr(C)]
+pub struct FramesResult {
+    pub res: RawIterator,
+    pub err: ErrorType,
+}
+
+impl PanicFallback for FramesResult {
+    fn panic_fallback() -> Self {
+        FramesResult {
+            res: RawIterator::null(),
+            err: ErrorType::IMAGE_ERROR_PANIC,
+        }
+    }
+}
+
+/// The frames of an animation, which stop after the first one that fails to decode.
+struct FrameIter {
+    frames: Frames<'static>,
+    failed: bool,
+}
+
+impl ThinIterator for FrameIter {
+    fn next(&mut self) -> *mut c_void {
+        if self.failed {
+            return std::ptr::null_mut();
+        }
+        let result = match self.frames.next() {
+            Some(Ok(frame)) => {
+                let (delay_numer, delay_denom) = frame.delay().numer_denom_ms();
+                FrameResult {
+                    left: frame.left(),
+                    top: frame.top(),
+                    delay_numer,
+                    delay_denom,
+                    image: ravel!({ image::DynamicImage::ImageRgba8(frame.into_buffer()) }),
+                    err: ErrorType::IMAGE_ERROR_NONE,
+                }
+            }
+            Some(Err(er)) => {
+                self.failed = true;
+                FrameResult {
+                    image: std::ptr::null_mut(),
+                    left: 0,
+                    top: 0,
+                    delay_numer: 0,
+                    delay_denom: 1,
+                    err: get_image_error(er),
+                }
+            }
+            None => return std::ptr::null_mut(),
+        };
+        Box::leak(Box::new(result)) as *mut _ as *mut c_void
+    }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<FrameResult>(item)
+    }
+}
+
+fn frames(bytes: Vec<u8>, format: ImageFormat) -> image::ImageResult<Option<Frames<'static>>> {
+    let r = Cursor::new(bytes);
+    Ok(Some(match format {
+        ImageFormat::IMAGE_FORMAT_GIF => GifDecoder::new(r)?.into_frames(),
+        ImageFormat::IMAGE_FORMAT_PNG => PngDecoder::new(r)?.apng()?.into_frames(),
+        ImageFormat::IMAGE_FORMAT_WEBP => WebPDecoder::new(r)?.into_frames(),
+        _ => return Ok(None),
+    }))
+}
+
+/// Decodes every frame of an animated GIF, APNG or WebP, returning an iterator of FrameResult pointers.
+///
+/// The bytes are copied, so they can be freed once this returns. If a frame fails to decode, the iterator yields a
+/// FrameResult with err set and a NULL image in its place, then ends. A PNG without an animation yields no frames.
+#[no_mangle]
+pub extern "C" fn dynamic_image_frames_from_memory(
+    bytes: *const u8,
+    size: usize,
+    format: ImageFormat,
+) -> FramesResult {
+    ffi_guard(|| {
+        assert!(!bytes.is_null());
+        assert!(bytes.is_aligned());
+        assert!(size < isize::MAX as usize);
+        let bytes = unsafe { std::slice::from_raw_parts(bytes, size) }.to_vec();
+        match frames(bytes, format) {
+            Ok(Some(frames)) => FramesResult {
+                res: make_raw_iterator(FrameIter {
+                    frames,
+                    failed: false,
+                }),
+                err: ErrorType::IMAGE_ERROR_NONE,
+            },
+            Ok(None) => FramesResult {
+                res: RawIterator::null(),
+                err: set_last_error(
+                    ErrorType::IMAGE_ERROR_UNSUPPORTED_FORMAT,
+                    "only GIF, PNG and WebP images can be animated",
+                ),
+            },
+            Err(er) => FramesResult {
+                res: RawIterator::null(),
+                err: get_image_error(er),
+            },
+        }
+    })
+}
+
+/// Passes writes on to the RustWriter until told to discard them, which lets an unfinished encoder be dropped
+/// without its trailer reaching the writer.
+struct GifWriter {
+    writer: RustWriter,
+    discard: bool,
+}
+
+impl Write for GifWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.discard {
+            true => Ok(buf.len()),
+            false => self.writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.discard {
+            true => Ok(()),
+            false => self.writer.flush(),
+        }
+    }
+}
+
+struct GifEncoderState {
+    // Made on the first frame, since the canvas takes its size.
+    encoder: Option<gif::Encoder<GifWriter>>,
+    writer: RustWriter,
+    repeat: Option<gif::Repeat>,
+}
+
+impl Drop for GifEncoderState {
+    fn drop(&mut self) {
+        if let Some(encoder) = &mut self.encoder {
+            encoder.get_mut().discard = true;
+        }
+    }
+}
+
+fn gif_error(er: gif::EncodingError) -> image::ImageError {
+    match er {
+        gif::EncodingError::Io(er) => image::ImageError::IoError(er),
+        er => image::ImageError::Encoding(image::error::EncodingError::new(
+            image::ImageFormat::Gif.into(),
+            er,
+        )),
+    }
+}
+
+/// Handle for writing an animated GIF one frame at a time. Get one from gif_encoder_new.
+#[repr(C)]
+pub struct GifEncoder {
+    inner: *mut c_void,
+}
+
+impl GifEncoder {
+    fn to_real_mut(&mut self) -> &mut GifEncoderState {
+        assert!(!self.inner.is_null());
+        assert!(self.inner.is_aligned());
+        unsafe { (self.inner as *mut GifEncoderState).as_mut().unwrap() }
+    }
+}
+
+/// Starts a GIF that gets written through the given RustWriter. The RustWriter is copied, so only its user_data has to stay alive.
+///
+/// The canvas takes the size of the first frame. The handle is freed by gif_encoder_finish, or by gif_encoder_free to give up on the GIF.
+#[no_mangle]
+pub extern "C" fn gif_encoder_new(w: *mut RustWriter) -> *mut GifEncoder {
+    ffi_guard(|| {
+        assert!(!w.is_null());
+        let writer = unsafe { *w };
+        let state = GifEncoderState {
+            encoder: None,
+            writer,
+            repeat: None,
+        };
+        Box::leak(Box::new(GifEncoder {
+            inner: Box::leak(Box::new(state)) as *mut GifEncoderState as *mut c_void,
+        })) as *mut GifEncoder
+    })
+}
+
+/// Sets how many times the animation repeats, where 0 means that it loops forever.
+#[no_mangle]
+pub extern "C" fn gif_encoder_set_repeat(enc: *mut GifEncoder, count: u16) -> ErrorType {
+    ffi_guard(|| {
+        let repeat = match count {
+            0 => gif::Repeat::Infinite,
+            n => gif::Repeat::Finite(n),
+        };
+        let state = unsafe { enc.as_mut() }.unwrap().to_real_mut();
+        state.repeat = Some(repeat);
+        match state.encoder.as_mut().map(|a| a.set_repeat(repeat)) {
+            Some(Err(er)) => get_image_error(gif_error(er)),
+            _ => ErrorType::IMAGE_ERROR_NONE,
+        }
+    })
+}
+
+/// Appends the image as the next frame, shown for delay_ms milliseconds. The image is converted to RGBA8 and left untouched.
+///
+/// GIF delays are in hundredths of a second, so delay_ms is rounded to the nearest multiple of 10. Anything above 0
+/// stays at least 10, since many viewers treat a delay of 0 as "as fast as possible".
+#[no_mangle]
+pub extern "C" fn gif_encoder_add_frame(
+    enc: *mut GifEncoder,
+    img: *mut DynamicImage,
+    delay_ms: u32,
+) -> ErrorType {
+    ffi_guard(|| {
+        let mut buf = unravel!(img).to_rgba8();
+        let (Ok(width), Ok(height)) = (u16::try_from(buf.width()), u16::try_from(buf.height()))
+        else {
+            return set_last_error(
+                ErrorType::IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH,
+                "a GIF frame can be at most 65535 pixels on a side",
+            );
+        };
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut buf, 1);
+        let delay = (delay_ms.saturating_add(5) / 10).max(u32::from(delay_ms > 0));
+        frame.delay = delay.try_into().unwrap_or(u16::MAX);
+        frame.dispose = gif::DisposalMethod::Background;
+        let state = unsafe { enc.as_mut() }.unwrap().to_real_mut();
+        let encoder = match &mut state.encoder {
+            Some(a) => a,
+            None => {
+                let writer = GifWriter {
+                    writer: state.writer,
+                    discard: false,
+                };
+                let mut encoder = match gif::Encoder::new(writer, width, height, &[]) {
+                    Ok(a) => a,
+                    Err(er) => return get_image_error(gif_error(er)),
+                };
+                if let Some(Err(er)) = state.repeat.map(|a| encoder.set_repeat(a)) {
+                    return get_image_error(gif_error(er));
+                }
+                state.encoder.insert(encoder)
+            }
+        };
+        match encoder.write_frame(&frame) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(gif_error(er)),
+        }
+    })
+}
+
+/// Writes the end of the GIF, flushes the writer and frees the handle.
+///
+/// If no frame was added, nothing is written at all. The handle is freed even if writing fails.
+#[no_mangle]
+pub extern "C" fn gif_encoder_finish(enc: *mut GifEncoder) -> ErrorType {
+    ffi_guard(|| {
+        assert!(!enc.is_null());
+        let og = unsafe { Box::from_raw(enc) };
+        assert!(!og.inner.is_null());
+        let mut state = unsafe { Box::from_raw(og.inner as *mut GifEncoderState) };
+        let mut writer = state.writer;
+        if let Some(encoder) = state.encoder.take() {
+            if let Err(er) = encoder.into_inner() {
+                return get_image_error(gif_error(er));
+            }
+        }
+        match writer.flush() {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(image::ImageError::IoError(er)),
+        }
+    })
+}
+
+/// Frees the handle without finishing the GIF, so nothing more is written to the writer. Does nothing if enc is NULL.
+///
+/// Whatever was already written stays written, which leaves the writer with a GIF that has no end.
+#[no_mangle]
+pub extern "C" fn gif_encoder_free(enc: *mut GifEncoder) {
+    ffi_guard(|| {
+        if !enc.is_null() {
+            let og = unsafe { Box::from_raw(enc) };
+            if !og.inner.is_null() {
+                std::mem::drop(unsafe { Box::from_raw(og.inner as *mut GifEncoderState) });
+            }
+        }
+    })
+}
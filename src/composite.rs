@@ -0,0 +1,172 @@
+use image::{imageops, DynamicImage as Real};
+
+use crate::{
+    ffi_guard,
+    pixel::{convert_to, copy_into, with_same_buffers},
+    DynamicImage,
+};
+
+/// How dynamic_image_blend combines the colors of the top layer with the ones under it.
+/// These follow the separable blend modes of the W3C compositing spec.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum BlendMode {
+    BLEND_MODE_NORMAL,
+    BLEND_MODE_MULTIPLY,
+    BLEND_MODE_SCREEN,
+    BLEND_MODE_OVERLAY,
+    BLEND_MODE_DARKEN,
+    BLEND_MODE_LIGHTEN,
+    /// Clamped to white.
+    BLEND_MODE_ADD,
+    BLEND_MODE_DIFFERENCE,
+}
+
+impl BlendMode {
+    fn apply(self, b: f32, s: f32) -> f32 {
+        match self {
+            BlendMode::BLEND_MODE_NORMAL => s,
+            BlendMode::BLEND_MODE_MULTIPLY => b * s,
+            BlendMode::BLEND_MODE_SCREEN => b + s - b * s,
+            BlendMode::BLEND_MODE_OVERLAY => match b <= 0.5 {
+                true => 2.0 * b * s,
+                false => 1.0 - 2.0 * (1.0 - b) * (1.0 - s),
+            },
+            BlendMode::BLEND_MODE_DARKEN => b.min(s),
+            BlendMode::BLEND_MODE_LIGHTEN => b.max(s),
+            BlendMode::BLEND_MODE_ADD => (b + s).min(1.0),
+            BlendMode::BLEND_MODE_DIFFERENCE => (b - s).abs(),
+        }
+    }
+
+    /// Blends s over b and composites the result with source-over, both with straight alpha.
+    fn blend(self, b: image::Rgba<f32>, s: image::Rgba<f32>, opacity: f32) -> image::Rgba<f32> {
+        let ab = b.0[3];
+        let a_s = s.0[3] * opacity;
+        let ao = a_s + ab * (1.0 - a_s);
+        if ao <= 0.0 {
+            return image::Rgba([0.0; 4]);
+        }
+        let mut out = [0.0, 0.0, 0.0, ao];
+        for ((o, cb), cs) in out.iter_mut().zip(b.0).zip(s.0).take(3) {
+            let cs = (1.0 - ab) * cs + ab * self.apply(cb, cs);
+            *o = (a_s * cs + ab * cb * (1.0 - a_s)) / ao;
+        }
+        image::Rgba(out)
+    }
+}
+
+fn blend_into(bottom: &mut Real, top: &Real, x: i64, y: i64, mode: BlendMode, opacity: f32) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    let x0 = x.max(0);
+    let y0 = y.max(0);
+    // Saturating keeps offsets near i64::MAX from wrapping around, and leaves x1 <= x0 so nothing is drawn.
+    let x1 = x
+        .saturating_add(top.width() as i64)
+        .min(bottom.width() as i64);
+    let y1 = y
+        .saturating_add(top.height() as i64)
+        .min(bottom.height() as i64);
+    if x1 <= x0 || y1 <= y0 {
+        return;
+    }
+    let (w, h) = ((x1 - x0) as u32, (y1 - y0) as u32);
+    let layer = top
+        .crop_imm((x0 - x) as u32, (y0 - y) as u32, w, h)
+        .into_rgba32f();
+    let mut region = bottom.crop_imm(x0 as u32, y0 as u32, w, h).into_rgba32f();
+    for (b, s) in region.pixels_mut().zip(layer.pixels()) {
+        *b = mode.blend(*b, *s, opacity);
+    }
+    copy_into(bottom, &Real::from(region), x0 as u32, y0 as u32).unwrap();
+}
+
+/// Converts top to bottom's color type, so that the two can be handed to imageops together.
+fn matching(bottom: &Real, top: &Real) -> Real {
+    convert_to(top, bottom.color())
+}
+
+/// Draws top over bottom with its top left corner at (x, y), blending by top's alpha.
+/// The offsets can be negative or past the edge, in which case only the part that overlaps is drawn.
+#[no_mangle]
+pub extern "C" fn dynamic_image_overlay(
+    bottom: *mut DynamicImage,
+    top: *mut DynamicImage,
+    x: i64,
+    y: i64,
+) {
+    ffi_guard(|| {
+        let (src, dst) = (unravel!(top), unravel!(bottom));
+        // Converting top would throw its alpha away, so blend it in f32 instead.
+        if src.color().has_alpha() && !dst.color().has_alpha() {
+            let top = src.clone();
+            return blend_into(
+                unravel_mut!(bottom),
+                &top,
+                x,
+                y,
+                BlendMode::BLEND_MODE_NORMAL,
+                1.0,
+            );
+        }
+        let top = matching(dst, src);
+        with_same_buffers!(
+            unravel_mut!(bottom),
+            &top,
+            |b, t| imageops::overlay(b, t, x, y),
+            unreachable!()
+        )
+    })
+}
+
+/// Same as dynamic_image_overlay, but copies top's pixels over bottom's without blending.
+#[no_mangle]
+pub extern "C" fn dynamic_image_replace(
+    bottom: *mut DynamicImage,
+    top: *mut DynamicImage,
+    x: i64,
+    y: i64,
+) {
+    ffi_guard(|| {
+        let top = matching(unravel!(bottom), unravel!(top));
+        with_same_buffers!(
+            unravel_mut!(bottom),
+            &top,
+            |b, t| imageops::replace(b, t, x, y),
+            unreachable!()
+        )
+    })
+}
+
+/// Repeats top across the whole of bottom, starting from the top left corner.
+#[no_mangle]
+pub extern "C" fn dynamic_image_tile(bottom: *mut DynamicImage, top: *mut DynamicImage) {
+    ffi_guard(|| {
+        let top = matching(unravel!(bottom), unravel!(top));
+        with_same_buffers!(
+            unravel_mut!(bottom),
+            &top,
+            |b, t| imageops::tile(b, t),
+            unreachable!()
+        )
+    })
+}
+
+/// Draws top over bottom with its top left corner at (x, y), combining colors with the given blend mode.
+///
+/// opacity scales top's alpha and is clamped to 0..1. The blending is done in f32, so 16-bit and f32 images
+/// keep their precision. Like dynamic_image_overlay, the offsets can be negative or past the edge.
+#[no_mangle]
+pub extern "C" fn dynamic_image_blend(
+    bottom: *mut DynamicImage,
+    top: *mut DynamicImage,
+    x: i64,
+    y: i64,
+    mode: BlendMode,
+    opacity: f32,
+) {
+    ffi_guard(|| {
+        let top = unravel!(top).clone();
+        blend_into(unravel_mut!(bottom), &top, x, y, mode, opacity)
+    })
+}
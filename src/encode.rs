@@ -0,0 +1,209 @@
+use std::io::{Seek, Write};
+
+use image::codecs::{
+    avif::AvifEncoder,
+    jpeg::JpegEncoder,
+    png::{self, PngEncoder},
+    pnm::{self, PnmEncoder},
+    tiff::TiffEncoder,
+    webp::WebPEncoder,
+};
+
+use crate::{
+    ffi_guard, get_image_error,
+    icc::{icc_of, matches_color, with_icc},
+    DynamicImage, ErrorType, RustWriter,
+};
+
+/// Which encoder an EncodeOptions struct is for, and so which field of its union is valid.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum EncodeOptionsType {
+    ENCODE_OPTIONS_JPEG,
+    ENCODE_OPTIONS_PNG,
+    /// WebP is only encoded losslessly, so there are no options for it.
+    ENCODE_OPTIONS_WEBP,
+    ENCODE_OPTIONS_AVIF,
+    ENCODE_OPTIONS_PNM,
+    /// TIFF has no options, but is here so that it can be written like the others.
+    ENCODE_OPTIONS_TIFF,
+}
+
+/// Compression level of the PNG encoder.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum PngCompressionType {
+    PNG_COMPRESSION_DEFAULT,
+    PNG_COMPRESSION_FAST,
+    PNG_COMPRESSION_BEST,
+}
+
+/// Filter algorithm the PNG encoder runs over each scanline before compressing it.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum PngFilterType {
+    PNG_FILTER_NO_FILTER,
+    PNG_FILTER_SUB,
+    PNG_FILTER_UP,
+    PNG_FILTER_AVG,
+    PNG_FILTER_PAETH,
+    PNG_FILTER_ADAPTIVE,
+}
+
+/// Which of the PNM formats to write.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum PnmSubtype {
+    /// P1 or P4
+    PNM_SUBTYPE_BITMAP,
+    /// P2 or P5
+    PNM_SUBTYPE_GRAYMAP,
+    /// P3 or P6
+    PNM_SUBTYPE_PIXMAP,
+    /// P7
+    PNM_SUBTYPE_ARBITRARY_MAP,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct JpegOptions {
+    /// From 1 to 100, where 100 is the best quality.
+    pub quality: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PngOptions {
+    pub compression: PngCompressionType,
+    pub filter: PngFilterType,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AvifOptions {
+    /// From 1 to 10, where 1 is the slowest and smallest.
+    pub speed: u8,
+    /// From 1 to 100, where 100 is the best quality.
+    pub quality: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PnmOptions {
+    pub subtype: PnmSubtype,
+    /// Whether samples are written as ASCII text instead of binary. Ignored for PNM_SUBTYPE_ARBITRARY_MAP.
+    pub ascii: bool,
+}
+
+/// Union used for the EncodeOptions struct
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union EncodeOptionsUnion {
+    pub jpeg: JpegOptions,
+    pub png: PngOptions,
+    pub avif: AvifOptions,
+    pub pnm: PnmOptions,
+}
+
+/// Options for one of the encoders. ty represents which encoder to use and which field of val to read them from.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct EncodeOptions {
+    pub ty: EncodeOptionsType,
+    pub val: EncodeOptionsUnion,
+}
+
+impl PngCompressionType {
+    fn to_real(self) -> png::CompressionType {
+        match self {
+            PngCompressionType::PNG_COMPRESSION_DEFAULT => png::CompressionType::Default,
+            PngCompressionType::PNG_COMPRESSION_FAST => png::CompressionType::Fast,
+            PngCompressionType::PNG_COMPRESSION_BEST => png::CompressionType::Best,
+        }
+    }
+}
+
+impl PngFilterType {
+    fn to_real(self) -> png::FilterType {
+        match self {
+            PngFilterType::PNG_FILTER_NO_FILTER => png::FilterType::NoFilter,
+            PngFilterType::PNG_FILTER_SUB => png::FilterType::Sub,
+            PngFilterType::PNG_FILTER_UP => png::FilterType::Up,
+            PngFilterType::PNG_FILTER_AVG => png::FilterType::Avg,
+            PngFilterType::PNG_FILTER_PAETH => png::FilterType::Paeth,
+            PngFilterType::PNG_FILTER_ADAPTIVE => png::FilterType::Adaptive,
+        }
+    }
+}
+
+impl PnmOptions {
+    fn to_real(self) -> pnm::PnmSubtype {
+        let encoding = match self.ascii {
+            true => pnm::SampleEncoding::Ascii,
+            false => pnm::SampleEncoding::Binary,
+        };
+        match self.subtype {
+            PnmSubtype::PNM_SUBTYPE_BITMAP => pnm::PnmSubtype::Bitmap(encoding),
+            PnmSubtype::PNM_SUBTYPE_GRAYMAP => pnm::PnmSubtype::Graymap(encoding),
+            PnmSubtype::PNM_SUBTYPE_PIXMAP => pnm::PnmSubtype::Pixmap(encoding),
+            PnmSubtype::PNM_SUBTYPE_ARBITRARY_MAP => pnm::PnmSubtype::ArbitraryMap,
+        }
+    }
+}
+
+fn write_with_options<W: Write + Seek>(
+    img: &image::DynamicImage,
+    icc: Option<&[u8]>,
+    w: W,
+    options: EncodeOptions,
+) -> image::ImageResult<()> {
+    let icc = icc.filter(|a| matches_color(a, img.color()));
+    let val = options.val;
+    match options.ty {
+        EncodeOptionsType::ENCODE_OPTIONS_JPEG => img.write_with_encoder(with_icc(
+            JpegEncoder::new_with_quality(w, unsafe { val.jpeg }.quality),
+            icc,
+        )),
+        EncodeOptionsType::ENCODE_OPTIONS_PNG => {
+            let png = unsafe { val.png };
+            img.write_with_encoder(with_icc(
+                PngEncoder::new_with_quality(w, png.compression.to_real(), png.filter.to_real()),
+                icc,
+            ))
+        }
+        EncodeOptionsType::ENCODE_OPTIONS_WEBP => {
+            img.write_with_encoder(with_icc(WebPEncoder::new_lossless(w), icc))
+        }
+        EncodeOptionsType::ENCODE_OPTIONS_AVIF => {
+            let avif = unsafe { val.avif };
+            img.write_with_encoder(with_icc(
+                AvifEncoder::new_with_speed_quality(w, avif.speed, avif.quality),
+                icc,
+            ))
+        }
+        EncodeOptionsType::ENCODE_OPTIONS_PNM => {
+            let subtype = unsafe { val.pnm }.to_real();
+            img.write_with_encoder(PnmEncoder::new(w).with_subtype(subtype))
+        }
+        EncodeOptionsType::ENCODE_OPTIONS_TIFF => {
+            img.write_with_encoder(with_icc(TiffEncoder::new(w), icc))
+        }
+    }
+}
+
+/// Same as dynamic_image_write_to, but with control over the encoder's settings. The format is taken from options.ty.
+#[no_mangle]
+pub extern "C" fn dynamic_image_write_to_with_options(
+    this: *mut DynamicImage,
+    w: *mut RustWriter,
+    options: EncodeOptions,
+) -> ErrorType {
+    ffi_guard(|| {
+        assert!(!w.is_null());
+        let w = unsafe { w.as_mut() }.unwrap();
+        match write_with_options(unravel!(this), icc_of(this), w, options) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
+}
@@ -96,6 +96,8 @@ const char* image_error::what() const noexcept {
     case internal::IMAGE_ERROR_UNKNOWN:
       return "IMAGE_ERROR_UNKNOWN";
       break;
+    case internal::IMAGE_ERROR_PANIC:
+      return "rs_image panicked while handling the call";
   }
   return "No error";
 }
@@ -0,0 +1,53 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+};
+
+use crate::{guard::ffi_guard, ErrorType};
+
+struct LastError {
+    code: ErrorType,
+    message: CString,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+/// Records the error for rs_image_last_error_code and rs_image_last_error_message on this thread, then hands the code back.
+pub fn set_last_error(code: ErrorType, message: impl Into<String>) -> ErrorType {
+    let message = message.into().replace('\0', "");
+    LAST_ERROR.with(|e| {
+        *e.borrow_mut() = Some(LastError {
+            code,
+            message: CString::new(message).unwrap_or_default(),
+        })
+    });
+    code
+}
+
+/// Returns the code of the last error reported on this thread, or IMAGE_ERROR_NONE if there hasn't been one.
+///
+/// Like errno, this isn't cleared by calls that succeed, so only check it after a call reported an error.
+#[no_mangle]
+pub extern "C" fn rs_image_last_error_code() -> ErrorType {
+    ffi_guard(|| {
+        LAST_ERROR.with(|e| match e.borrow().as_ref() {
+            Some(a) => a.code,
+            None => ErrorType::IMAGE_ERROR_NONE,
+        })
+    })
+}
+
+/// Returns a human readable description of the last error reported on this thread, or NULL if there hasn't been one.
+///
+/// The string is owned by the library and stays valid until the next error happens on the same thread, so copy it if you need to keep it.
+#[no_mangle]
+pub extern "C" fn rs_image_last_error_message() -> *const c_char {
+    ffi_guard(|| {
+        LAST_ERROR.with(|e| match e.borrow().as_ref() {
+            Some(a) => a.message.as_ptr(),
+            None => std::ptr::null(),
+        })
+    })
+}
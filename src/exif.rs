@@ -0,0 +1,501 @@
+use std::{ffi::c_void, io::Cursor};
+
+use image::{ImageDecoder, ImageReader, ImageResult};
+
+use crate::{
+    decode_guessed,
+    error::set_last_error,
+    ffi_guard, get_image_error,
+    guard::PanicFallback,
+    iter::{make_raw_iterator, RawIterator},
+    thin::{free_boxed, ThinIterator},
+    ErrorType, LoadFromMemoryResult,
+};
+
+/// Which directory of the EXIF data a tag was found in.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum ExifIfd {
+    /// IFD0, describing the main image.
+    EXIF_IFD_PRIMARY,
+    /// IFD1, describing the embedded thumbnail.
+    EXIF_IFD_THUMBNAIL,
+    EXIF_IFD_EXIF,
+    EXIF_IFD_GPS,
+    EXIF_IFD_INTEROP,
+}
+
+/// A single EXIF tag, as returned by the iterator from dynamic_image_exif_tags.
+///
+/// field_type is the TIFF type of the value (1 = BYTE, 2 = ASCII, 3 = SHORT, 4 = LONG, 5 = RATIONAL, 7 = UNDEFINED, ...)
+/// and count is how many values of that type there are. data points at the len bytes of the values inside the
+/// iterator's copy of the EXIF block, in the byte order given by little_endian, and stays valid until the iterator
+/// is freed. Use exif_tag_number to read numeric values without worrying about either.
+#[repr(C)]
+pub struct ExifTag {
+    pub ifd: ExifIfd,
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    pub data: *const u8,
+    pub len: usize,
+    pub little_endian: bool,
+}
+
+#[repr(C)]
+pub struct ExifTagsResult {
+    pub res: RawIterator,
+    pub err: ErrorType,
+}
+
+impl PanicFallback for ExifTagsResult {
+    fn panic_fallback() -> Self {
+        ExifTagsResult {
+            res: RawIterator::null(),
+            err: ErrorType::IMAGE_ERROR_PANIC,
+        }
+    }
+}
+
+impl PanicFallback for f64 {
+    fn panic_fallback() -> Self {
+        f64::NAN
+    }
+}
+
+struct Entry {
+    ifd: ExifIfd,
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    offset: usize,
+    len: usize,
+}
+
+/// A TIFF structured EXIF block, as handed out by ImageDecoder::exif_metadata.
+struct Tiff<'a> {
+    buf: &'a [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b = self.buf.get(at..at.checked_add(2)?)?.try_into().ok()?;
+        Some(match self.little_endian {
+            true => u16::from_le_bytes(b),
+            false => u16::from_be_bytes(b),
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let b = self.buf.get(at..at.checked_add(4)?)?.try_into().ok()?;
+        Some(match self.little_endian {
+            true => u32::from_le_bytes(b),
+            false => u32::from_be_bytes(b),
+        })
+    }
+
+    /// Reads the directory at offset into out, following the pointers to the EXIF, GPS and interop directories.
+    /// Returns the offset of the next directory in the chain, or 0 if there isn't one.
+    fn read_ifd(
+        &self,
+        offset: usize,
+        ifd: ExifIfd,
+        out: &mut Vec<Entry>,
+        seen: &mut Vec<usize>,
+    ) -> Option<usize> {
+        // Directories that point back at each other would otherwise never end.
+        if seen.contains(&offset) {
+            return None;
+        }
+        seen.push(offset);
+        let entries = self.u16(offset)? as usize;
+        for i in 0..entries {
+            let at = offset + 2 + i * 12;
+            let (Some(tag), Some(field_type), Some(count)) =
+                (self.u16(at), self.u16(at + 2), self.u32(at + 4))
+            else {
+                return None;
+            };
+            let Some(len) = type_size(field_type).and_then(|a| a.checked_mul(count as usize))
+            else {
+                continue;
+            };
+            let data = match len <= 4 {
+                true => at + 8,
+                false => match self.u32(at + 8) {
+                    Some(a) => a as usize,
+                    None => continue,
+                },
+            };
+            if data.checked_add(len).is_none_or(|end| end > self.buf.len()) {
+                continue;
+            }
+            out.push(Entry {
+                ifd,
+                tag,
+                field_type,
+                count,
+                offset: data,
+                len,
+            });
+            let sub = match tag {
+                0x8769 => ExifIfd::EXIF_IFD_EXIF,
+                0x8825 => ExifIfd::EXIF_IFD_GPS,
+                0xA005 => ExifIfd::EXIF_IFD_INTEROP,
+                _ => continue,
+            };
+            if let Some(sub_offset) = self.u32(data) {
+                self.read_ifd(sub_offset as usize, sub, out, seen);
+            }
+        }
+        self.u32(offset + 2 + entries * 12).map(|a| a as usize)
+    }
+}
+
+/// How many bytes a single value of a TIFF field type takes up.
+fn type_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+/// Lists every tag in the primary, thumbnail, EXIF, GPS and interop directories of an EXIF block.
+fn parse(buf: &[u8]) -> Option<(bool, Vec<Entry>)> {
+    let little_endian = match buf.get(0..4)? {
+        [0x49, 0x49, 42, 0] => true,
+        [0x4d, 0x4d, 0, 42] => false,
+        _ => return None,
+    };
+    let tiff = Tiff { buf, little_endian };
+    let mut out = vec![];
+    let mut seen = vec![];
+    let next = tiff.read_ifd(
+        tiff.u32(4)? as usize,
+        ExifIfd::EXIF_IFD_PRIMARY,
+        &mut out,
+        &mut seen,
+    )?;
+    if next != 0 {
+        tiff.read_ifd(next, ExifIfd::EXIF_IFD_THUMBNAIL, &mut out, &mut seen);
+    }
+    Some((little_endian, out))
+}
+
+struct ExifTags {
+    exif: Vec<u8>,
+    little_endian: bool,
+    tags: std::vec::IntoIter<Entry>,
+}
+
+impl ThinIterator for ExifTags {
+    fn next(&mut self) -> *mut c_void {
+        match self.tags.next() {
+            Some(e) => Box::leak(Box::new(ExifTag {
+                ifd: e.ifd,
+                tag: e.tag,
+                field_type: e.field_type,
+                count: e.count,
+                data: self.exif[e.offset..].as_ptr(),
+                len: e.len,
+                little_endian: self.little_endian,
+            })) as *mut _ as *mut c_void,
+            None => std::ptr::null_mut(),
+        }
+    }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<ExifTag>(item)
+    }
+}
+
+fn decoder(bytes: &[u8]) -> ImageResult<impl ImageDecoder + '_> {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_decoder()
+}
+
+fn exif_of(bytes: &[u8]) -> ImageResult<Option<Vec<u8>>> {
+    decoder(bytes)?.exif_metadata()
+}
+
+fn bytes_from<'a>(bytes: *const u8, size: usize) -> &'a [u8] {
+    assert!(!bytes.is_null());
+    assert!(size < isize::MAX as usize);
+    unsafe { std::slice::from_raw_parts(bytes, size) }
+}
+
+/// Same as dynamic_image_load_from_memory, but rotates and flips the image the way its EXIF Orientation tag says to,
+/// so that it comes out the right way up. If the EXIF data can't be read, the image is left the way it was stored.
+#[no_mangle]
+pub extern "C" fn dynamic_image_load_from_memory_oriented(
+    bytes: *const u8,
+    size: usize,
+) -> LoadFromMemoryResult {
+    ffi_guard(|| {
+        let cursor = Cursor::new(bytes_from(bytes, size));
+        decode_guessed(cursor, image::Limits::default(), true).into()
+    })
+}
+
+/// Copies the raw EXIF block out of an encoded image into a buffer that has to be released with rs_image_buffer_free.
+///
+/// The block starts with the TIFF header, so it can be handed to any EXIF parser. If the image has no EXIF data, or
+/// its format doesn't carry any, *out is set to NULL and *out_len to 0 and IMAGE_ERROR_NONE is returned.
+#[no_mangle]
+pub extern "C" fn dynamic_image_exif_bytes(
+    bytes: *const u8,
+    size: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> ErrorType {
+    ffi_guard(|| {
+        assert!(!out.is_null() && !out_len.is_null());
+        unsafe {
+            *out = std::ptr::null_mut();
+            *out_len = 0;
+        }
+        match exif_of(bytes_from(bytes, size)) {
+            Ok(Some(exif)) => {
+                let exif = exif.into_boxed_slice();
+                unsafe {
+                    *out_len = exif.len();
+                    *out = Box::into_raw(exif) as *mut u8;
+                }
+                ErrorType::IMAGE_ERROR_NONE
+            }
+            Ok(None) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
+}
+
+/// Parses the EXIF data of an encoded image, returning an iterator of ExifTag pointers.
+///
+/// The bytes are copied, so they can be freed once this returns. An image without EXIF data gives an empty iterator,
+/// while EXIF data that isn't valid TIFF gives IMAGE_ERROR_DECODING.
+#[no_mangle]
+pub extern "C" fn dynamic_image_exif_tags(bytes: *const u8, size: usize) -> ExifTagsResult {
+    ffi_guard(|| {
+        let exif = match exif_of(bytes_from(bytes, size)) {
+            Ok(a) => a.unwrap_or_default(),
+            Err(er) => {
+                return ExifTagsResult {
+                    res: RawIterator::null(),
+                    err: get_image_error(er),
+                }
+            }
+        };
+        let (little_endian, tags) = match exif.is_empty() {
+            true => (true, vec![]),
+            false => match parse(&exif) {
+                Some(a) => a,
+                None => {
+                    return ExifTagsResult {
+                        res: RawIterator::null(),
+                        err: set_last_error(
+                            ErrorType::IMAGE_ERROR_DECODING,
+                            "the EXIF data is not valid TIFF",
+                        ),
+                    }
+                }
+            },
+        };
+        ExifTagsResult {
+            res: make_raw_iterator(ExifTags {
+                exif,
+                little_endian,
+                tags: tags.into_iter(),
+            }),
+            err: ErrorType::IMAGE_ERROR_NONE,
+        }
+    })
+}
+
+/// Reads the value at index of a numeric tag as a double, dividing rationals out.
+/// Returns NaN for ASCII and UNDEFINED tags, or if index is past count.
+#[no_mangle]
+pub extern "C" fn exif_tag_number(tag: *const ExifTag, index: u32) -> f64 {
+    ffi_guard(|| {
+        let tag = unsafe { tag.as_ref() }.expect("tag is null!");
+        let Some(size) = type_size(tag.field_type) else {
+            return f64::NAN;
+        };
+        if index >= tag.count {
+            return f64::NAN;
+        }
+        let data = unsafe { std::slice::from_raw_parts(tag.data, tag.len) };
+        let tiff = Tiff {
+            buf: &data[index as usize * size..],
+            little_endian: tag.little_endian,
+        };
+        let number = match tag.field_type {
+            1 => tiff.buf.first().map(|&a| a as f64),
+            6 => tiff.buf.first().map(|&a| a as i8 as f64),
+            3 => tiff.u16(0).map(|a| a as f64),
+            8 => tiff.u16(0).map(|a| a as i16 as f64),
+            4 | 13 => tiff.u32(0).map(|a| a as f64),
+            9 => tiff.u32(0).map(|a| a as i32 as f64),
+            5 => tiff
+                .u32(0)
+                .zip(tiff.u32(4))
+                .map(|(n, d)| n as f64 / d as f64),
+            10 => tiff
+                .u32(0)
+                .zip(tiff.u32(4))
+                .map(|(n, d)| n as i32 as f64 / d as i32 as f64),
+            11 => tiff.u32(0).map(|a| f32::from_bits(a) as f64),
+            12 => {
+                let hi = tiff.u32(0).zip(tiff.u32(4));
+                hi.map(|(a, b)| match tag.little_endian {
+                    true => f64::from_bits((b as u64) << 32 | a as u64),
+                    false => f64::from_bits((a as u64) << 32 | b as u64),
+                })
+            }
+            _ => None,
+        };
+        number.unwrap_or(f64::NAN)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_bytes(v: u16, le: bool) -> [u8; 2] {
+        match le {
+            true => v.to_le_bytes(),
+            false => v.to_be_bytes(),
+        }
+    }
+
+    fn u32_bytes(v: u32, le: bool) -> [u8; 4] {
+        match le {
+            true => v.to_le_bytes(),
+            false => v.to_be_bytes(),
+        }
+    }
+
+    /// A SHORT value stored inline, left justified in the entry's four value bytes.
+    fn short(v: u16, le: bool) -> [u8; 4] {
+        let [a, b] = u16_bytes(v, le);
+        [a, b, 0, 0]
+    }
+
+    fn header(le: bool, ifd0: u32) -> Vec<u8> {
+        let mut out = match le {
+            true => b"II\x2a\0".to_vec(),
+            false => b"MM\0\x2a".to_vec(),
+        };
+        out.extend(u32_bytes(ifd0, le));
+        out
+    }
+
+    /// Appends a directory of (tag, field type, count, value or offset) entries followed by the next pointer.
+    fn ifd(out: &mut Vec<u8>, le: bool, entries: &[(u16, u16, u32, [u8; 4])], next: u32) {
+        out.extend(u16_bytes(entries.len() as u16, le));
+        for &(tag, field_type, count, value) in entries {
+            out.extend(u16_bytes(tag, le));
+            out.extend(u16_bytes(field_type, le));
+            out.extend(u32_bytes(count, le));
+            out.extend(value);
+        }
+        out.extend(u32_bytes(next, le));
+    }
+
+    /// IFD0 at 8 with an inline Orientation, an ASCII description stored after the directories and a pointer to
+    /// an EXIF directory at 50 holding an inline ExifVersion.
+    fn sample(le: bool) -> Vec<u8> {
+        let mut out = header(le, 8);
+        ifd(
+            &mut out,
+            le,
+            &[
+                (0x0112, 3, 1, short(6, le)),
+                (0x010E, 2, 12, u32_bytes(68, le)),
+                (0x8769, 4, 1, u32_bytes(50, le)),
+            ],
+            0,
+        );
+        assert_eq!(out.len(), 50);
+        ifd(&mut out, le, &[(0x9000, 7, 4, *b"0232")], 0);
+        assert_eq!(out.len(), 68);
+        out.extend(b"hello world\0");
+        out
+    }
+
+    #[test]
+    fn both_byte_orders() {
+        for le in [true, false] {
+            let buf = sample(le);
+            let (little_endian, tags) = parse(&buf).unwrap();
+            assert_eq!(little_endian, le);
+            let numbers: Vec<u16> = tags.iter().map(|a| a.tag).collect();
+            assert_eq!(numbers, [0x0112, 0x010E, 0x8769, 0x9000]);
+            let tiff = Tiff {
+                buf: &buf,
+                little_endian: le,
+            };
+            assert_eq!(tiff.u16(tags[0].offset), Some(6));
+            assert_eq!(&buf[tags[1].offset..][..tags[1].len], b"hello world\0");
+            assert!(matches!(tags[3].ifd, ExifIfd::EXIF_IFD_EXIF));
+            assert_eq!(&buf[tags[3].offset..][..tags[3].len], b"0232");
+        }
+    }
+
+    #[test]
+    fn directory_cycles_end() {
+        // IFD0 names itself as the next directory, and the EXIF pointer leads back to IFD0 as well.
+        let mut buf = header(true, 8);
+        ifd(
+            &mut buf,
+            true,
+            &[
+                (0x0112, 3, 1, short(1, true)),
+                (0x8769, 4, 1, u32_bytes(8, true)),
+            ],
+            8,
+        );
+        let (_, tags) = parse(&buf).unwrap();
+        assert_eq!(tags.len(), 2);
+    }
+
+    #[test]
+    fn values_out_of_range_are_skipped() {
+        let mut buf = header(true, 8);
+        ifd(
+            &mut buf,
+            true,
+            &[
+                (0x010E, 2, 12, u32_bytes(0xFFFF_FFF0, true)),
+                (0x010F, 2, 8, u32_bytes(70, true)),
+                (0x0110, 5, u32::MAX, u32_bytes(8, true)),
+                (0x0111, 0x99, 1, u32_bytes(0, true)),
+                (0x0112, 3, 1, short(3, true)),
+            ],
+            0,
+        );
+        // The second string starts inside the buffer but runs off its end.
+        assert_eq!(buf.len(), 74);
+        let (_, tags) = parse(&buf).unwrap();
+        let numbers: Vec<u16> = tags.iter().map(|a| a.tag).collect();
+        assert_eq!(numbers, [0x0112]);
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let buf = sample(true);
+        assert!(parse(&buf[..3]).is_none());
+        assert!(parse(&buf[..7]).is_none());
+        // The header points past the end.
+        let mut far = header(false, 1000);
+        far.extend([0; 8]);
+        assert!(parse(&far).is_none());
+        // IFD0 says it has three entries, but the data stops halfway through the second.
+        assert!(parse(&buf[..30]).is_none());
+        assert!(parse(b"XX\x2a\0\x08\0\0\0").is_none());
+    }
+}
@@ -0,0 +1,52 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::{error::set_last_error, ErrorType};
+
+/// The value an exported function hands back to C when it panicked, since unwinding into C is undefined behaviour.
+pub trait PanicFallback {
+    fn panic_fallback() -> Self;
+}
+
+/// Runs the body of an exported function, returning `T::panic_fallback()` instead of letting a panic escape.
+pub fn ffi_guard<T: PanicFallback>(f: impl FnOnce() -> T) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(a) => a,
+        Err(payload) => {
+            let message = match payload.downcast_ref::<&str>() {
+                Some(a) => a.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(a) => a.clone(),
+                    None => "unknown panic".to_string(),
+                },
+            };
+            set_last_error(ErrorType::IMAGE_ERROR_PANIC, message);
+            T::panic_fallback()
+        }
+    }
+}
+
+impl<T> PanicFallback for *mut T {
+    fn panic_fallback() -> Self {
+        std::ptr::null_mut()
+    }
+}
+
+impl<T> PanicFallback for *const T {
+    fn panic_fallback() -> Self {
+        std::ptr::null()
+    }
+}
+
+macro_rules! default_fallback {
+    ($($t:ty),*) => {
+        $(
+            impl PanicFallback for $t {
+                fn panic_fallback() -> Self {
+                    Default::default()
+                }
+            }
+        )*
+    };
+}
+
+default_fallback!((), bool, u32, usize);
@@ -0,0 +1,654 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, tiff::TiffEncoder, webp::WebPEncoder},
+    DynamicImage as Real, ImageEncoder, ImageFormat,
+};
+
+use crate::{
+    error::set_last_error,
+    ffi_guard,
+    pixel::convert_to,
+    transform::{invert, Matrix},
+    DynamicImage, ErrorType,
+};
+
+/// Hands the profile to the encoder if there is one. Encoders for formats that can't carry a profile refuse it
+/// with an error, which is ignored so the image is still written, just without the profile.
+pub fn with_icc<E: ImageEncoder>(mut encoder: E, icc: Option<&[u8]>) -> E {
+    if let Some(icc) = icc {
+        let _ = encoder.set_icc_profile(icc.to_vec());
+    }
+    encoder
+}
+
+/// Whether the data color space in the header of an ICC profile can describe pixels of the given color type.
+/// CMYK, Lab and other profiles never can, since decoders hand those images out as RGB.
+pub fn matches_color(icc: &[u8], color: image::ColorType) -> bool {
+    match icc.get(16..20) {
+        Some(b"RGB ") => color.has_color(),
+        Some(b"GRAY") => !color.has_color(),
+        _ => false,
+    }
+}
+
+/// Same as DynamicImage::write_to, but embeds the ICC profile for the formats that support one.
+/// A profile that doesn't match the image's color type is left out.
+pub fn write_with_icc<W: Write + Seek>(
+    img: &image::DynamicImage,
+    icc: Option<&[u8]>,
+    mut w: W,
+    format: ImageFormat,
+) -> image::ImageResult<()> {
+    let icc = icc.filter(|a| matches_color(a, img.color()));
+    if icc.is_none() {
+        return img.write_to(w, format);
+    }
+    match format {
+        ImageFormat::Png => img.write_with_encoder(with_icc(PngEncoder::new(&mut w), icc)),
+        ImageFormat::Jpeg => img.write_with_encoder(with_icc(JpegEncoder::new(&mut w), icc)),
+        ImageFormat::WebP => {
+            img.write_with_encoder(with_icc(WebPEncoder::new_lossless(&mut w), icc))
+        }
+        ImageFormat::Tiff => img.write_with_encoder(with_icc(TiffEncoder::new(&mut w), icc)),
+        _ => img.write_to(w, format),
+    }
+}
+
+/// Same as DynamicImage::save_with_format, but embeds the ICC profile for the formats that support one.
+pub fn save_with_icc(
+    img: &image::DynamicImage,
+    icc: Option<&[u8]>,
+    path: &Path,
+    format: ImageFormat,
+) -> image::ImageResult<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_with_icc(img, icc, &mut file, format)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// The ICC profile attached to a handle.
+pub fn icc_of<'a>(this: *mut DynamicImage) -> Option<&'a [u8]> {
+    assert!(!this.is_null());
+    unsafe { this.as_ref() }.unwrap().icc()
+}
+
+/// Points at the ICC profile attached to the image and writes its length to out_len, or returns NULL if it has none.
+///
+/// Images loaded from a file that embeds a profile keep it, and it's written back out when encoding to PNG, JPEG,
+/// WebP or TIFF. Profiles whose color space doesn't match the decoded pixels, like the CMYK profile of a CMYK JPEG
+/// that decodes to RGB, are dropped on load. The pointer stays valid until the profile is replaced or the image is
+/// freed. Functions that return a new image don't carry the profile over, with the exception of dynamic_image_clone.
+#[no_mangle]
+pub extern "C" fn dynamic_image_icc_profile(
+    this: *mut DynamicImage,
+    out_len: *mut usize,
+) -> *const u8 {
+    ffi_guard(|| {
+        assert!(!out_len.is_null());
+        let icc = icc_of(this);
+        unsafe { *out_len = icc.map_or(0, |a| a.len()) };
+        icc.map_or(std::ptr::null(), |a| a.as_ptr())
+    })
+}
+
+/// Attaches a copy of the given ICC profile to the image, replacing any it already had.
+/// Passing NULL or a length of 0 removes the profile.
+///
+/// The profile isn't checked or applied to the pixels, it's only written out alongside them, and left out if its
+/// color space doesn't match the image's color type.
+#[no_mangle]
+pub extern "C" fn dynamic_image_set_icc_profile(
+    this: *mut DynamicImage,
+    icc: *const u8,
+    len: usize,
+) {
+    ffi_guard(|| {
+        assert!(!this.is_null());
+        let icc = match icc.is_null() || len == 0 {
+            true => None,
+            false => Some(unsafe { std::slice::from_raw_parts(icc, len) }.to_vec()),
+        };
+        unsafe { this.as_mut() }.unwrap().set_icc(icc);
+    })
+}
+
+/// Which ICC rendering intent dynamic_image_convert_profile uses.
+///
+/// Matrix/TRC profiles only define a colorimetric mapping, so perceptual and saturation behave like relative
+/// colorimetric. Absolute colorimetric additionally scales by the ratio of the two media white points.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum RenderingIntent {
+    RENDERING_INTENT_PERCEPTUAL,
+    RENDERING_INTENT_RELATIVE_COLORIMETRIC,
+    RENDERING_INTENT_SATURATION,
+    RENDERING_INTENT_ABSOLUTE_COLORIMETRIC,
+}
+
+/// The ICC profile connection space white point.
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// A tone reproduction curve, mapping encoded values to linear light.
+enum Curve {
+    Gamma(f64),
+    Table(Vec<f64>),
+    /// The parameters g, a, b, c, d, e and f of the most general parametric curve, which every other
+    /// parametric type is normalized to: (a*x + b)^g + e when x >= d, c*x + f otherwise.
+    Parametric([f64; 7]),
+}
+
+impl Curve {
+    fn eval(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(t) => {
+                let pos = x * (t.len() - 1) as f64;
+                let i = (pos as usize).min(t.len() - 2);
+                t[i] + (t[i + 1] - t[i]) * (pos - i as f64)
+            }
+            &Curve::Parametric([g, a, b, c, d, e, f]) => match x >= d {
+                true => (a * x + b).max(0.0).powf(g) + e,
+                false => c * x + f,
+            },
+        }
+    }
+
+    fn eval_inverse(&self, y: f64) -> f64 {
+        let y = y.clamp(0.0, 1.0);
+        let x = match self {
+            Curve::Gamma(g) => y.powf(1.0 / g),
+            Curve::Table(t) => {
+                // Tables are monotonic, so find the segment that crosses y and interpolate inside it.
+                let i = t.partition_point(|&a| a < y);
+                match i {
+                    0 => 0.0,
+                    _ if i >= t.len() => 1.0,
+                    _ => {
+                        let span = t[i] - t[i - 1];
+                        let frac = match span > 0.0 {
+                            true => (y - t[i - 1]) / span,
+                            false => 0.0,
+                        };
+                        (i as f64 - 1.0 + frac) / (t.len() - 1) as f64
+                    }
+                }
+            }
+            &Curve::Parametric([g, a, b, c, d, e, f]) => {
+                match y >= (a * d + b).max(0.0).powf(g) + e {
+                    true => ((y - e).max(0.0).powf(1.0 / g) - b) / a,
+                    false if c != 0.0 => (y - f) / c,
+                    false => 0.0,
+                }
+            }
+        };
+        x.clamp(0.0, 1.0)
+    }
+}
+
+/// The parts of a matrix/TRC profile needed to get colors to and from the profile connection space.
+enum Shaper {
+    Rgb {
+        matrix: Matrix,
+        inverse: Matrix,
+        curves: Box<[Curve; 3]>,
+    },
+    Gray(Curve),
+}
+
+struct Profile {
+    shaper: Shaper,
+    white: [f64; 3],
+}
+
+type ProfileError = (ErrorType, &'static str);
+
+fn malformed() -> ProfileError {
+    (
+        ErrorType::IMAGE_ERROR_PARAMETER_MALFORMED,
+        "the ICC profile is malformed",
+    )
+}
+
+/// Reads the tag table of an ICC profile.
+struct Icc<'a>(&'a [u8]);
+
+impl Icc<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        Some(u16::from_be_bytes(
+            self.0.get(at..at.checked_add(2)?)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(
+            self.0.get(at..at.checked_add(4)?)?.try_into().ok()?,
+        ))
+    }
+
+    fn s15f16(&self, at: usize) -> Option<f64> {
+        Some(self.u32(at)? as i32 as f64 / 65536.0)
+    }
+
+    fn tag(&self, sig: &[u8; 4]) -> Option<&[u8]> {
+        let count = self.u32(128)? as usize;
+        (0..count.min(self.0.len() / 12)).find_map(|i| {
+            let at = 132 + i * 12;
+            if self.0.get(at..at + 4)? != sig {
+                return None;
+            }
+            let offset = self.u32(at + 4)? as usize;
+            let size = self.u32(at + 8)? as usize;
+            self.0.get(offset..offset.checked_add(size)?)
+        })
+    }
+
+    fn has_tag(&self, sig: &[u8; 4]) -> bool {
+        self.tag(sig).is_some()
+    }
+
+    fn xyz(&self, sig: &[u8; 4]) -> Result<Option<[f64; 3]>, ProfileError> {
+        let Some(tag) = self.tag(sig) else {
+            return Ok(None);
+        };
+        let tag = Icc(tag);
+        if tag.0.get(0..4) != Some(b"XYZ ") {
+            return Err(malformed());
+        }
+        match (tag.s15f16(8), tag.s15f16(12), tag.s15f16(16)) {
+            (Some(x), Some(y), Some(z)) => Ok(Some([x, y, z])),
+            _ => Err(malformed()),
+        }
+    }
+
+    fn curve(&self, sig: &[u8; 4]) -> Result<Option<Curve>, ProfileError> {
+        let Some(tag) = self.tag(sig) else {
+            return Ok(None);
+        };
+        let tag = Icc(tag);
+        let curve = match tag.0.get(0..4) {
+            Some(b"curv") => {
+                let count = tag.u32(8).ok_or_else(malformed)? as usize;
+                match count {
+                    0 => Some(Curve::Gamma(1.0)),
+                    1 => tag.u16(12).map(|a| Curve::Gamma(a as f64 / 256.0)),
+                    _ => (0..count)
+                        .map(|i| tag.u16(12 + i * 2).map(|a| a as f64 / 65535.0))
+                        .collect::<Option<Vec<_>>>()
+                        .map(Curve::Table),
+                }
+            }
+            Some(b"para") => {
+                let params = |n: usize| {
+                    (0..n)
+                        .map(|i| tag.s15f16(12 + i * 4))
+                        .collect::<Option<Vec<_>>>()
+                };
+                match tag.u16(8) {
+                    Some(0) => params(1).map(|p| [p[0], 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+                    Some(1) => params(3).map(|p| [p[0], p[1], p[2], 0.0, -p[2] / p[1], 0.0, 0.0]),
+                    Some(2) => params(4).map(|p| [p[0], p[1], p[2], 0.0, -p[2] / p[1], p[3], p[3]]),
+                    Some(3) => params(5).map(|p| [p[0], p[1], p[2], p[3], p[4], 0.0, 0.0]),
+                    Some(4) => params(7).map(|p| [p[0], p[1], p[2], p[3], p[4], p[5], p[6]]),
+                    _ => None,
+                }
+                .map(Curve::Parametric)
+            }
+            _ => None,
+        };
+        let valid = match &curve {
+            Some(Curve::Gamma(g)) => *g > 0.0,
+            Some(Curve::Table(_)) => true,
+            Some(Curve::Parametric(p)) => {
+                p.iter().all(|a| a.is_finite()) && p[0] > 0.0 && p[1] != 0.0
+            }
+            None => false,
+        };
+        match valid {
+            true => Ok(curve),
+            false => Err(malformed()),
+        }
+    }
+}
+
+impl Profile {
+    fn parse(bytes: &[u8]) -> Result<Profile, ProfileError> {
+        let icc = Icc(bytes);
+        if bytes.len() < 132 || bytes.get(36..40) != Some(b"acsp") {
+            return Err(malformed());
+        }
+        let unsupported = |msg| Err((ErrorType::IMAGE_ERROR_UNSUPPORTED_COLOR, msg));
+        let gray = match &bytes[16..20] {
+            b"RGB " => false,
+            b"GRAY" => true,
+            _ => return unsupported("only RGB and gray ICC profiles can be converted"),
+        };
+        if &bytes[20..24] != b"XYZ " {
+            return unsupported("only ICC profiles with an XYZ connection space can be converted");
+        }
+        let lut_only = || match icc.has_tag(b"A2B0") || icc.has_tag(b"B2A0") {
+            true => {
+                unsupported("only matrix/TRC ICC profiles can be converted, not LUT based ones")
+            }
+            false => Err(malformed()),
+        };
+        let shaper = match gray {
+            true => match icc.curve(b"kTRC")? {
+                Some(curve) => Shaper::Gray(curve),
+                None => return lut_only(),
+            },
+            false => {
+                let columns = (icc.xyz(b"rXYZ")?, icc.xyz(b"gXYZ")?, icc.xyz(b"bXYZ")?);
+                let curves = (
+                    icc.curve(b"rTRC")?,
+                    icc.curve(b"gTRC")?,
+                    icc.curve(b"bTRC")?,
+                );
+                let ((Some(r), Some(g), Some(b)), (Some(rc), Some(gc), Some(bc))) =
+                    (columns, curves)
+                else {
+                    return lut_only();
+                };
+                let matrix = [r[0], g[0], b[0], r[1], g[1], b[1], r[2], g[2], b[2]];
+                Shaper::Rgb {
+                    matrix,
+                    inverse: invert(&matrix).ok_or_else(malformed)?,
+                    curves: Box::new([rc, gc, bc]),
+                }
+            }
+        };
+        Ok(Profile {
+            shaper,
+            white: icc.xyz(b"wtpt")?.unwrap_or(D50),
+        })
+    }
+
+    /// The D50 adapted sRGB profile, used when no profile is given.
+    fn srgb() -> Profile {
+        let matrix = [
+            0.4360747, 0.3850649, 0.1430804, 0.2225045, 0.7168786, 0.0606169, 0.0139322, 0.0971045,
+            0.7141733,
+        ];
+        let curve = || {
+            Curve::Parametric([
+                2.4,
+                1.0 / 1.055,
+                0.055 / 1.055,
+                1.0 / 12.92,
+                0.04045,
+                0.0,
+                0.0,
+            ])
+        };
+        Profile {
+            shaper: Shaper::Rgb {
+                matrix,
+                inverse: invert(&matrix).unwrap(),
+                curves: Box::new([curve(), curve(), curve()]),
+            },
+            white: D50,
+        }
+    }
+
+    fn to_xyz(&self, c: [f64; 3]) -> [f64; 3] {
+        match &self.shaper {
+            Shaper::Rgb { matrix, curves, .. } => {
+                let lin = [
+                    curves[0].eval(c[0]),
+                    curves[1].eval(c[1]),
+                    curves[2].eval(c[2]),
+                ];
+                mul_vec(matrix, lin)
+            }
+            Shaper::Gray(curve) => D50.map(|a| a * curve.eval(c[0])),
+        }
+    }
+
+    fn xyz_to_device(&self, xyz: [f64; 3]) -> [f64; 3] {
+        match &self.shaper {
+            Shaper::Rgb {
+                inverse, curves, ..
+            } => {
+                let lin = mul_vec(inverse, xyz);
+                [
+                    curves[0].eval_inverse(lin[0]),
+                    curves[1].eval_inverse(lin[1]),
+                    curves[2].eval_inverse(lin[2]),
+                ]
+            }
+            Shaper::Gray(curve) => [curve.eval_inverse(xyz[1]); 3],
+        }
+    }
+}
+
+fn mul_vec(m: &Matrix, v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0] * v[0] + m[1] * v[1] + m[2] * v[2],
+        m[3] * v[0] + m[4] * v[1] + m[5] * v[2],
+        m[6] * v[0] + m[7] * v[1] + m[8] * v[2],
+    ]
+}
+
+fn profile_from(icc: *const u8, len: usize) -> Option<&'static [u8]> {
+    match icc.is_null() || len == 0 {
+        true => None,
+        false => Some(unsafe { std::slice::from_raw_parts(icc, len) }),
+    }
+}
+
+fn parse_or_srgb(icc: Option<&[u8]>) -> Result<Profile, ProfileError> {
+    icc.map_or_else(|| Ok(Profile::srgb()), Profile::parse)
+}
+
+/// Converts the pixels of the image from the src ICC profile to the dst one, and attaches dst to the image.
+///
+/// If src is NULL the image's own profile is used, and if dst is NULL the image is converted to sRGB and left
+/// without a profile. An image with neither is taken to be sRGB. Only matrix/TRC (matrix-shaper) RGB and gray
+/// profiles are supported; CMYK, Lab and LUT based profiles give IMAGE_ERROR_UNSUPPORTED_COLOR and leave the
+/// image untouched, as do a gray dst for a color image and an RGB dst for a gray one. The conversion is done
+/// in f32, so 16-bit and f32 images keep their precision, but values are clamped to the gamut of dst.
+#[no_mangle]
+pub extern "C" fn dynamic_image_convert_profile(
+    this: *mut DynamicImage,
+    src_icc: *const u8,
+    src_len: usize,
+    dst_icc: *const u8,
+    dst_len: usize,
+    intent: RenderingIntent,
+) -> ErrorType {
+    ffi_guard(|| {
+        let dst_bytes = profile_from(dst_icc, dst_len);
+        let profiles = parse_or_srgb(profile_from(src_icc, src_len).or(icc_of(this)))
+            .and_then(|src| Ok((src, parse_or_srgb(dst_bytes)?)));
+        let (src, dst) = match profiles {
+            Ok(a) => a,
+            Err((code, msg)) => return set_last_error(code, msg),
+        };
+        let img = unravel!(this);
+        if matches!(src.shaper, Shaper::Gray(_)) && img.color().has_color() {
+            return set_last_error(
+                ErrorType::IMAGE_ERROR_UNSUPPORTED_COLOR,
+                "a gray ICC profile can't describe a color image",
+            );
+        }
+        // dst gets attached to the image and embedded when it's encoded, so it has to match the pixels.
+        if dst_bytes.is_some() && matches!(dst.shaper, Shaper::Gray(_)) == img.color().has_color() {
+            return set_last_error(
+                ErrorType::IMAGE_ERROR_UNSUPPORTED_COLOR,
+                "the destination ICC profile doesn't match the color type of the image",
+            );
+        }
+        let scale = match intent {
+            RenderingIntent::RENDERING_INTENT_ABSOLUTE_COLORIMETRIC => {
+                [0, 1, 2].map(|i| src.white[i] / dst.white[i])
+            }
+            _ => [1.0; 3],
+        };
+        let mut buf = img.to_rgba32f();
+        for p in buf.pixels_mut() {
+            let xyz = src.to_xyz([p[0], p[1], p[2]].map(|a| a as f64));
+            let out = dst.xyz_to_device([0, 1, 2].map(|i| xyz[i] * scale[i]));
+            for i in 0..3 {
+                p[i] = out[i] as f32;
+            }
+        }
+        *unravel_mut!(this) = convert_to(&Real::from(buf), img.color());
+        unsafe { this.as_mut() }
+            .unwrap()
+            .set_icc(dst_bytes.map(|a| a.to_vec()));
+        ErrorType::IMAGE_ERROR_NONE
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s15f16(a: f64) -> [u8; 4] {
+        ((a * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    fn xyz(v: [f64; 3]) -> Vec<u8> {
+        let mut out = b"XYZ \0\0\0\0".to_vec();
+        v.iter().for_each(|&a| out.extend(s15f16(a)));
+        out
+    }
+
+    fn para(kind: u16, params: &[f64]) -> Vec<u8> {
+        let mut out = b"para\0\0\0\0".to_vec();
+        out.extend(kind.to_be_bytes());
+        out.extend([0, 0]);
+        params.iter().for_each(|&a| out.extend(s15f16(a)));
+        out
+    }
+
+    fn curv(values: &[u16]) -> Vec<u8> {
+        let mut out = b"curv\0\0\0\0".to_vec();
+        out.extend((values.len() as u32).to_be_bytes());
+        values.iter().for_each(|a| out.extend(a.to_be_bytes()));
+        out
+    }
+
+    /// Lays out a profile with the given data color space and tags.
+    fn profile(space: &[u8; 4], tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut header = vec![0; 128];
+        header[16..20].copy_from_slice(space);
+        header[20..24].copy_from_slice(b"XYZ ");
+        header[36..40].copy_from_slice(b"acsp");
+        let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut data = vec![];
+        let start = 128 + 4 + 12 * tags.len();
+        for (sig, body) in tags {
+            table.extend(*sig);
+            table.extend(((start + data.len()) as u32).to_be_bytes());
+            table.extend((body.len() as u32).to_be_bytes());
+            data.extend(body);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+        let mut out = header;
+        out.extend(table);
+        out.extend(data);
+        let len = out.len() as u32;
+        out[0..4].copy_from_slice(&len.to_be_bytes());
+        out
+    }
+
+    fn srgb_bytes() -> Vec<u8> {
+        let trc = para(3, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045]);
+        profile(
+            b"RGB ",
+            &[
+                (b"wtpt", xyz(D50)),
+                (b"rXYZ", xyz([0.4360747, 0.2225045, 0.0139322])),
+                (b"gXYZ", xyz([0.3850649, 0.7168786, 0.0971045])),
+                (b"bXYZ", xyz([0.1430804, 0.0606169, 0.7141733])),
+                (b"rTRC", trc.clone()),
+                (b"gTRC", trc.clone()),
+                (b"bTRC", trc),
+            ],
+        )
+    }
+
+    fn error_of(bytes: &[u8]) -> Option<ErrorType> {
+        Profile::parse(bytes).err().map(|e| e.0)
+    }
+
+    #[test]
+    fn srgb_round_trip_is_identity() {
+        let builtin = Profile::srgb();
+        let parsed = Profile::parse(&srgb_bytes()).ok().unwrap();
+        for r in 0..=10 {
+            for g in 0..=10 {
+                for b in 0..=10 {
+                    let c = [r, g, b].map(|a| a as f64 / 10.0);
+                    let out = parsed.xyz_to_device(builtin.to_xyz(c));
+                    for i in 0..3 {
+                        assert!((out[i] - c[i]).abs() < 1e-3, "{c:?} -> {out:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn curves_invert() {
+        let table: Vec<u16> = (0..256)
+            .map(|i| ((i as f64 / 255.0).powf(1.8) * 65535.0).round() as u16)
+            .collect();
+        let curves = [
+            curv(&[]),
+            curv(&[(2.2 * 256.0) as u16]),
+            curv(&table),
+            para(0, &[2.2]),
+            para(1, &[2.2, 1.0, 0.0]),
+            para(2, &[2.2, 0.9, 0.1, 0.0]),
+            para(3, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045]),
+            para(4, &[2.4, 0.95, 0.05, 0.08, 0.04, 0.0, 0.0]),
+        ];
+        for body in curves {
+            let bytes = profile(b"GRAY", &[(b"kTRC", body)]);
+            let curve = Icc(&bytes).curve(b"kTRC").ok().flatten().unwrap();
+            for i in 0..=100 {
+                let x = i as f64 / 100.0;
+                let back = curve.eval_inverse(curve.eval(x));
+                assert!((back - x).abs() < 2e-3, "{x} -> {back}");
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_profiles_are_malformed() {
+        let bytes = srgb_bytes();
+        let malformed = Some(ErrorType::IMAGE_ERROR_PARAMETER_MALFORMED);
+        assert!(error_of(&bytes).is_none());
+        assert!(error_of(&bytes[..100]) == malformed);
+        assert!(error_of(&bytes[..bytes.len() - 20]) == malformed);
+        assert!(error_of(b"not an icc profile at all") == malformed);
+
+        // A tag offset that points past the end of the profile.
+        let mut bytes = srgb_bytes();
+        bytes[132 + 12 + 4..132 + 12 + 8].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+        assert!(error_of(&bytes) == malformed);
+
+        // A curve whose count runs past the end of its tag.
+        let mut body = curv(&[0, 65535]);
+        body[8..12].copy_from_slice(&1000u32.to_be_bytes());
+        let bytes = profile(b"GRAY", &[(b"kTRC", body)]);
+        assert!(error_of(&bytes) == malformed);
+    }
+
+    #[test]
+    fn lut_profiles_are_unsupported() {
+        let unsupported = Some(ErrorType::IMAGE_ERROR_UNSUPPORTED_COLOR);
+        let lut = profile(b"RGB ", &[(b"wtpt", xyz(D50)), (b"A2B0", vec![0; 48])]);
+        assert!(error_of(&lut) == unsupported);
+        let cmyk = profile(b"CMYK", &[(b"A2B0", vec![0; 48])]);
+        assert!(error_of(&cmyk) == unsupported);
+    }
+}
@@ -26,6 +26,124 @@ DynamicImage::DynamicImage(std::vector<char> data) {
   this->img = er.res;
 };
 
+DynamicImage* DynamicImage::load_oriented(const std::vector<char>& data) {
+  auto er = internal::dynamic_image_load_from_memory_oriented(
+      (const uint8_t*)data.data(), data.size());
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return new DynamicImage(er.res);
+};
+
+DynamicImage* DynamicImage::load_with_limits(const std::vector<char>& data,
+                                             DecodeLimits limits) {
+  auto er = internal::dynamic_image_load_from_memory_with_limits(
+      (uint8_t*)data.data(), data.size(), limits);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return new DynamicImage(er.res);
+};
+
+std::vector<Frame> DynamicImage::frames(const std::vector<char>& data,
+                                        ImageFormat format) {
+  auto er = internal::dynamic_image_frames_from_memory(
+      (const uint8_t*)data.data(), data.size(), format);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  Iterator<internal::FrameResult> iter(new internal::RawIterator(er.res));
+  std::vector<Frame> out;
+  while (auto frame = iter.next()) {
+    if (frame->err != internal::IMAGE_ERROR_NONE) {
+      for (auto& done : out) {
+        delete done.image;
+      }
+      throw new image_error(frame->err);
+    }
+    out.push_back({new DynamicImage(frame->image), frame->left, frame->top,
+                   frame->delay_numer, frame->delay_denom});
+  }
+  return out;
+};
+
+DynamicImage::DynamicImage(uint32_t width, uint32_t height, ColorType color) {
+  this->img = internal::dynamic_image_new(width, height, color);
+};
+
+DynamicImage* DynamicImage::from_raw(uint32_t width,
+                                     uint32_t height,
+                                     ColorType color,
+                                     const void* data,
+                                     size_t len,
+                                     size_t stride) {
+  auto er = internal::dynamic_image_from_raw(width, height, color, data, len,
+                                             stride);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return new DynamicImage(er.res);
+};
+
+DynamicImage* DynamicImage::open(const char* path) {
+  auto er = internal::dynamic_image_open(path);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return new DynamicImage(er.res);
+};
+
+DynamicImage* DynamicImage::open_with_format(const char* path,
+                                             ImageFormat format) {
+  auto er = internal::dynamic_image_open_with_format(path, format);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return new DynamicImage(er.res);
+};
+
+ProbeResult DynamicImage::probe(const std::vector<char>& data) {
+  auto er =
+      internal::dynamic_image_probe((const uint8_t*)data.data(), data.size());
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return er;
+};
+
+ProbeResult DynamicImage::probe(RustReader* r) {
+  auto er = internal::dynamic_image_probe_reader(r);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return er;
+};
+
+std::vector<uint8_t> DynamicImage::exif_bytes(const std::vector<char>& data) {
+  uint8_t* bytes;
+  uintptr_t size;
+  auto er = internal::dynamic_image_exif_bytes((const uint8_t*)data.data(),
+                                               data.size(), &bytes, &size);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+  if (bytes == NULL) {
+    return {};
+  }
+  std::vector<uint8_t> out(bytes, bytes + size);
+  internal::rs_image_buffer_free(bytes, size);
+  return out;
+};
+
+Iterator<ExifTag> DynamicImage::exif_tags(const std::vector<char>& data) {
+  auto er = internal::dynamic_image_exif_tags((const uint8_t*)data.data(),
+                                              data.size());
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return new internal::RawIterator(er.res);
+};
+
 DynamicImage::~DynamicImage() {
   internal::dynamic_image_free(this->img);
 };
@@ -37,6 +155,120 @@ Rgba DynamicImage::get_pixel(uint32_t x, uint32_t y) {
   return internal::dynamic_image_get_pixel(this->img, x, y);
 };
 
+void DynamicImage::put_pixel(uint32_t x, uint32_t y, Rgba color) {
+  auto er = internal::dynamic_image_put_pixel(this->img, x, y, color);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+};
+
+Rgba16 DynamicImage::get_pixel16(uint32_t x, uint32_t y) {
+  auto er = internal::dynamic_image_get_pixel16(this->img, x, y);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return er.color;
+};
+
+Rgba32F DynamicImage::get_pixel32f(uint32_t x, uint32_t y) {
+  auto er = internal::dynamic_image_get_pixel32f(this->img, x, y);
+  if (er.err != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er.err);
+  }
+  return er.color;
+};
+
+void DynamicImage::put_pixel16(uint32_t x, uint32_t y, Rgba16 color) {
+  auto er = internal::dynamic_image_put_pixel16(this->img, x, y, color);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+};
+
+void DynamicImage::put_pixel32f(uint32_t x, uint32_t y, Rgba32F color) {
+  auto er = internal::dynamic_image_put_pixel32f(this->img, x, y, color);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+};
+
+DynamicImage* DynamicImage::clone() {
+  return new DynamicImage(internal::dynamic_image_clone(this->img));
+};
+
+std::vector<uint8_t> DynamicImage::icc_profile() {
+  size_t len = 0;
+  auto icc = internal::dynamic_image_icc_profile(this->img, &len);
+  if (icc == nullptr) {
+    return {};
+  }
+  return std::vector<uint8_t>(icc, icc + len);
+};
+
+void DynamicImage::set_icc_profile(const std::vector<uint8_t>& icc) {
+  internal::dynamic_image_set_icc_profile(this->img, icc.data(), icc.size());
+};
+
+void DynamicImage::convert_profile(const std::vector<uint8_t>& src_icc,
+                                   const std::vector<uint8_t>& dst_icc,
+                                   RenderingIntent intent) {
+  auto er = internal::dynamic_image_convert_profile(
+      this->img, src_icc.data(), src_icc.size(), dst_icc.data(),
+      dst_icc.size(), intent);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+};
+
+void DynamicImage::copy_from(DynamicImage& src, uint32_t x, uint32_t y) {
+  auto er = internal::dynamic_image_copy_from(this->img, src.img, x, y);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+};
+
+void DynamicImage::overlay(DynamicImage& top, int64_t x, int64_t y) {
+  internal::dynamic_image_overlay(this->img, top.img, x, y);
+};
+
+void DynamicImage::replace(DynamicImage& top, int64_t x, int64_t y) {
+  internal::dynamic_image_replace(this->img, top.img, x, y);
+};
+
+void DynamicImage::tile(DynamicImage& top) {
+  internal::dynamic_image_tile(this->img, top.img);
+};
+
+void DynamicImage::blend(DynamicImage& top,
+                         int64_t x,
+                         int64_t y,
+                         BlendMode mode,
+                         float opacity) {
+  internal::dynamic_image_blend(this->img, top.img, x, y, mode, opacity);
+};
+
+BytesView DynamicImage::bytes_view() {
+  return internal::dynamic_image_bytes_view(this->img);
+};
+
+BytesViewMut DynamicImage::bytes_view_mut() {
+  return internal::dynamic_image_bytes_view_mut(this->img);
+};
+
+RawPixelBuffer DynamicImage::into_raw_buffer() {
+  auto buf = internal::dynamic_image_into_raw_buffer(this->img);
+  // The handle is freed either way, which leaves nothing for the destructor.
+  this->img = nullptr;
+  if (buf.data == NULL) {
+    throw new image_error(internal::rs_image_last_error_code());
+  }
+  return buf;
+};
+
+void DynamicImage::fill(Rgba color) {
+  internal::dynamic_image_fill(this->img, color);
+};
+
 uint32_t DynamicImage::height() {
   return internal::dynamic_image_height(this->img);
 };
@@ -45,19 +277,142 @@ bool DynamicImage::in_bounds(uint32_t x, uint32_t y) {
   return internal::dynamic_image_in_bounds(this->img, x, y);
 };
 
-Iterator<PixelResult*> DynamicImage::pixels() {
+Iterator<PixelResult> DynamicImage::pixels() {
   return new internal::RawIterator(internal::dynamic_image_pixels(this->img));
 }
 
+Iterator<PixelResult16> DynamicImage::pixels16() {
+  return new internal::RawIterator(internal::dynamic_image_pixels16(this->img));
+}
+
+Iterator<PixelResult32F> DynamicImage::pixels32f() {
+  return new internal::RawIterator(
+      internal::dynamic_image_pixels32f(this->img));
+}
+
+std::vector<uint8_t> DynamicImage::encode(ImageFormat format) {
+  uintptr_t size;
+  auto bytes = internal::dynamic_image_encode_to_buffer(this->img, format, &size);
+  if (bytes == NULL) {
+    throw new image_error(internal::rs_image_last_error_code());
+  }
+  std::vector<uint8_t> out(bytes, bytes + size);
+  internal::rs_image_buffer_free(bytes, size);
+  return out;
+}
+
+DynamicImage* DynamicImage::resize(uint32_t nwidth,
+                                   uint32_t nheight,
+                                   FilterType filter) {
+  return new DynamicImage(
+      internal::dynamic_image_resize(this->img, nwidth, nheight, filter));
+}
+DynamicImage* DynamicImage::resize(uint32_t nwidth,
+                                   uint32_t nheight,
+                                   FilterType filter,
+                                   ColorSpaceMode mode) {
+  return new DynamicImage(internal::dynamic_image_resize_with_color_space(
+      this->img, nwidth, nheight, filter, mode));
+}
+DynamicImage* DynamicImage::resize_exact(uint32_t nwidth,
+                                         uint32_t nheight,
+                                         FilterType filter) {
+  return new DynamicImage(
+      internal::dynamic_image_resize_exact(this->img, nwidth, nheight, filter));
+}
+DynamicImage* DynamicImage::resize_exact(uint32_t nwidth,
+                                         uint32_t nheight,
+                                         FilterType filter,
+                                         ColorSpaceMode mode) {
+  return new DynamicImage(internal::dynamic_image_resize_exact_with_color_space(
+      this->img, nwidth, nheight, filter, mode));
+}
+DynamicImage* DynamicImage::resize_to_fill(uint32_t nwidth,
+                                           uint32_t nheight,
+                                           FilterType filter) {
+  return new DynamicImage(internal::dynamic_image_resize_to_fill(
+      this->img, nwidth, nheight, filter));
+}
+DynamicImage* DynamicImage::resize_to_fill(uint32_t nwidth,
+                                           uint32_t nheight,
+                                           FilterType filter,
+                                           ColorSpaceMode mode) {
+  return new DynamicImage(
+      internal::dynamic_image_resize_to_fill_with_color_space(
+          this->img, nwidth, nheight, filter, mode));
+}
+DynamicImage* DynamicImage::thumbnail(uint32_t nwidth, uint32_t nheight) {
+  return new DynamicImage(
+      internal::dynamic_image_thumbnail(this->img, nwidth, nheight));
+}
+DynamicImage* DynamicImage::thumbnail(uint32_t nwidth,
+                                      uint32_t nheight,
+                                      ColorSpaceMode mode) {
+  return new DynamicImage(internal::dynamic_image_thumbnail_with_color_space(
+      this->img, nwidth, nheight, mode));
+}
+DynamicImage* DynamicImage::thumbnail_exact(uint32_t nwidth,
+                                            uint32_t nheight) {
+  return new DynamicImage(
+      internal::dynamic_image_thumbnail_exact(this->img, nwidth, nheight));
+}
+DynamicImage* DynamicImage::thumbnail_exact(uint32_t nwidth,
+                                            uint32_t nheight,
+                                            ColorSpaceMode mode) {
+  return new DynamicImage(
+      internal::dynamic_image_thumbnail_exact_with_color_space(
+          this->img, nwidth, nheight, mode));
+}
+
 DynamicImage* DynamicImage::blur(float sigma) {
   return new DynamicImage(internal::dynamic_image_blur(this->img, sigma));
 };
+
+DynamicImage* DynamicImage::blur(float sigma, ColorSpaceMode mode) {
+  return new DynamicImage(
+      internal::dynamic_image_blur_with_color_space(this->img, sigma, mode));
+};
+DynamicImage* DynamicImage::unsharpen(float sigma, int32_t threshold) {
+  return new DynamicImage(
+      internal::dynamic_image_unsharpen(this->img, sigma, threshold));
+}
+DynamicImage* DynamicImage::unsharpen(float sigma,
+                                      int32_t threshold,
+                                      ColorSpaceMode mode) {
+  return new DynamicImage(internal::dynamic_image_unsharpen_with_color_space(
+      this->img, sigma, threshold, mode));
+}
+DynamicImage* DynamicImage::resize_premultiplied(uint32_t nwidth,
+                                                 uint32_t nheight,
+                                                 FilterType filter,
+                                                 ColorSpaceMode mode) {
+  return new DynamicImage(internal::dynamic_image_resize_premultiplied(
+      this->img, nwidth, nheight, filter, mode));
+}
+DynamicImage* DynamicImage::resize_exact_premultiplied(uint32_t nwidth,
+                                                       uint32_t nheight,
+                                                       FilterType filter,
+                                                       ColorSpaceMode mode) {
+  return new DynamicImage(internal::dynamic_image_resize_exact_premultiplied(
+      this->img, nwidth, nheight, filter, mode));
+}
+DynamicImage* DynamicImage::blur_premultiplied(float sigma,
+                                               ColorSpaceMode mode) {
+  return new DynamicImage(
+      internal::dynamic_image_blur_premultiplied(this->img, sigma, mode));
+}
 DynamicImage* DynamicImage::brighten(float value) {
   return new DynamicImage(internal::dynamic_image_brighten(this->img, value));
 }
 void DynamicImage::invert() {
   internal::dynamic_image_invert(this->img);
 }
+void DynamicImage::premultiply() {
+  internal::dynamic_image_premultiply(this->img);
+}
+void DynamicImage::unpremultiply() {
+  internal::dynamic_image_unpremultiply(this->img);
+}
 internal::ColorType DynamicImage::color() {
   return internal::dynamic_image_color(this->img);
 }
@@ -79,6 +434,43 @@ DynamicImage* DynamicImage::rotate270() {
 DynamicImage* DynamicImage::rotate90() {
   return new DynamicImage(internal::dynamic_image_rotate90(this->img));
 }
+DynamicImage* DynamicImage::rotate(float degrees,
+                                   FilterType filter,
+                                   Rgba background,
+                                   bool expand_canvas) {
+  return new DynamicImage(internal::dynamic_image_rotate(
+      this->img, degrees, filter, background, expand_canvas));
+}
+DynamicImage* DynamicImage::rotate_premultiplied(float degrees,
+                                                 FilterType filter,
+                                                 Rgba background,
+                                                 bool expand_canvas) {
+  return new DynamicImage(internal::dynamic_image_rotate_premultiplied(
+      this->img, degrees, filter, background, expand_canvas));
+}
+DynamicImage* DynamicImage::affine(const float matrix[6],
+                                   uint32_t out_width,
+                                   uint32_t out_height,
+                                   FilterType filter) {
+  auto res = internal::dynamic_image_affine(this->img, matrix, out_width,
+                                            out_height, filter);
+  if (res == NULL) {
+    throw new image_error(internal::rs_image_last_error_code());
+  }
+  return new DynamicImage(res);
+}
+DynamicImage* DynamicImage::warp_perspective(const float src_quad[8],
+                                             uint32_t out_width,
+                                             uint32_t out_height,
+                                             FilterType filter,
+                                             Rgba fill) {
+  auto res = internal::dynamic_image_warp_perspective_with_fill(
+      this->img, src_quad, out_width, out_height, filter, fill);
+  if (res == NULL) {
+    throw new image_error(internal::rs_image_last_error_code());
+  }
+  return new DynamicImage(res);
+}
 DynamicImage* DynamicImage::into_luma16() {
   return new DynamicImage(internal::dynamic_image_into_luma16(this->img));
 }
@@ -109,4 +501,34 @@ DynamicImage* DynamicImage::into_rgba32f() {
 DynamicImage* DynamicImage::into_rgba8() {
   return new DynamicImage(internal::dynamic_image_into_rgba8(this->img));
 }
+
+GifEncoder::GifEncoder(RustWriter* w) {
+  this->enc = internal::gif_encoder_new(w);
+  if (this->enc == NULL) {
+    throw new image_error(internal::rs_image_last_error_code());
+  }
+}
+GifEncoder::~GifEncoder() {
+  internal::gif_encoder_free(this->enc);
+}
+void GifEncoder::set_repeat(uint16_t count) {
+  auto er = internal::gif_encoder_set_repeat(this->enc, count);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+}
+void GifEncoder::add_frame(DynamicImage& img, uint32_t delay_ms) {
+  auto er = internal::gif_encoder_add_frame(this->enc, img.img, delay_ms);
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+}
+void GifEncoder::finish() {
+  auto er = internal::gif_encoder_finish(this->enc);
+  // The handle is gone even if finishing failed.
+  this->enc = nullptr;
+  if (er != internal::IMAGE_ERROR_NONE) {
+    throw new image_error(er);
+  }
+}
 }  // namespace rs_image
\ No newline at end of file
@@ -7,6 +7,12 @@ pub enum Ordering {
     ORDERING_GREATER,
 }
 
+impl PanicFallback for Ordering {
+    fn panic_fallback() -> Self {
+        Ordering::ORDERING_EQUAL
+    }
+}
+
 impl Ordering {
     fn from_std(real: std::cmp::Ordering) -> Self {
         match real {
@@ -30,6 +36,15 @@ pub struct SizeHint {
     rhs: *mut usize,
 }
 
+impl PanicFallback for SizeHint {
+    fn panic_fallback() -> Self {
+        SizeHint {
+            lhs: 0,
+            rhs: std::ptr::null_mut(),
+        }
+    }
+}
+
 /*#[repr(C)]
 pub struct PartitionResult {
     lhs: *mut c_void,
@@ -41,6 +56,11 @@ pub struct PartitionResult {
 /// `__s` is expected to a pointer to something that implements Rust's std::iter::Iterator. You should not try and instantiate this yourself unless you have an object from Rust code.
 ///
 /// To make use of this, you should use the appropriate `iter_...` function.
+///
+/// Every iterator you're handed has to be released with iter_free once you're done with it, and every item it
+/// yields with iter_item_free. Adaptors like iter_map or iter_zip copy the iterators they're built from rather than
+/// taking them over, so those still need freeing after the adaptor is done. Items from an adaptor are boxes around
+/// the items of the iterator underneath, and freeing one doesn't free what it wraps.
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct RawIterator {
@@ -50,13 +70,40 @@ pub struct RawIterator {
     __size: usize,
 }
 
-use crate::thin::{BoxedThinIterator, ThinIterator};
+use crate::{
+    guard::{ffi_guard, PanicFallback},
+    thin::{BoxedThinIterator, ThinIterator},
+};
+
+impl RawIterator {
+    /// An iterator with nothing behind it, for functions that failed before they could make a real one.
+    pub fn null() -> Self {
+        RawIterator {
+            __s: std::ptr::null_mut(),
+            __size: 0,
+        }
+    }
+}
+
+impl PanicFallback for RawIterator {
+    fn panic_fallback() -> Self {
+        RawIterator::null()
+    }
+}
 
 pub extern "C" fn make_raw_iterator(mut iter: impl ThinIterator + Sized + 'static) -> RawIterator {
-    return RawIterator {
+    RawIterator {
         __s: Box::leak(Box::new(BoxedThinIterator::new(iter))) as *mut BoxedThinIterator,
         __size: 0,
-    };
+    }
+}
+
+impl RawIterator {
+    fn release_item(&self, item: *mut c_void) {
+        if !item.is_null() {
+            unsafe { (*self.__s).free_item(item) }
+        }
+    }
 }
 
 impl Iterator for RawIterator {
@@ -79,124 +126,202 @@ fn to_inner_mut<'a>(s: *mut RawIterator) -> &'a mut RawIterator {
 }
 #[no_mangle]
 extern "C" fn iter_next(s: *mut RawIterator) -> *mut c_void {
-    match to_inner_mut(s).next() {
+    ffi_guard(|| match to_inner_mut(s).next() {
         Some(a) => a,
         None => std::ptr::null_mut(),
-    }
+    })
 }
 
+/// The upper bound in the result, if there is one, has to be freed with iter_usize_free.
 #[no_mangle]
 extern "C" fn iter_size_hint(s: *mut RawIterator) -> SizeHint {
-    let r = to_inner(s).size_hint();
-    SizeHint {
-        lhs: r.0,
-        rhs: match r.1 {
-            Some(a) => Box::leak(Box::new(a)),
-            None => std::ptr::null_mut(),
-        },
-    }
+    ffi_guard(|| {
+        let r = to_inner(s).size_hint();
+        SizeHint {
+            lhs: r.0,
+            rhs: match r.1 {
+                Some(a) => Box::leak(Box::new(a)),
+                None => std::ptr::null_mut(),
+            },
+        }
+    })
 }
+/// Counts the remaining items, freeing them along the way.
 #[no_mangle]
 extern "C" fn iter_count(s: *mut RawIterator) -> usize {
-    to_inner(s).count()
+    ffi_guard(|| {
+        let inner = to_inner_mut(s);
+        let mut n = 0;
+        while let Some(a) = inner.next() {
+            inner.release_item(a);
+            n += 1;
+        }
+        n
+    })
 }
+/// Returns the last item, which is freed like any other item of the iterator. Every item before it is freed along the way.
 #[no_mangle]
 extern "C" fn iter_last(s: *mut RawIterator) -> *mut c_void {
-    match to_inner(s).last() {
-        Some(a) => Box::leak(Box::new(a)) as *mut _ as *mut c_void,
-        None => todo!(),
-    }
+    ffi_guard(|| {
+        let inner = to_inner_mut(s);
+        let mut last = std::ptr::null_mut();
+        while let Some(a) = inner.next() {
+            inner.release_item(last);
+            last = a;
+        }
+        last
+    })
 }
+/// Returns the nth item, which is freed like any other item of the iterator. The items skipped over are freed along the way.
 #[no_mangle]
 extern "C" fn iter_nth(s: *mut RawIterator, n: usize) -> *mut c_void {
-    match to_inner_mut(s).nth(n) {
-        Some(a) => Box::leak(Box::new(a)) as *mut _ as *mut c_void,
-        None => todo!(),
-    }
+    ffi_guard(|| {
+        let inner = to_inner_mut(s);
+        for _ in 0..n {
+            match inner.next() {
+                Some(a) => inner.release_item(a),
+                None => return std::ptr::null_mut(),
+            }
+        }
+        inner.next().unwrap_or(std::ptr::null_mut())
+    })
 }
+/// The items stepped over are freed as they're passed.
 #[no_mangle]
 extern "C" fn iter_step_by(s: *mut RawIterator, step: usize) -> RawIterator {
-    make_raw_iterator(unsafe { s.as_ref().unwrap().step_by(step) })
+    ffi_guard(|| {
+        assert!(step != 0);
+        let inner = *to_inner(s);
+        let mut i = 0;
+        make_raw_iterator(inner.filter(move |a| {
+            let keep = i % step == 0;
+            i += 1;
+            if !keep {
+                inner.release_item(*a);
+            }
+            keep
+        }))
+    })
 }
 #[no_mangle]
 extern "C" fn iter_chain(s: *mut RawIterator, other: RawIterator) -> RawIterator {
-    make_raw_iterator(unsafe { s.as_ref().unwrap().chain(other) })
+    ffi_guard(|| make_raw_iterator(unsafe { s.as_ref().unwrap().chain(other) }))
 }
 #[no_mangle]
 extern "C" fn iter_zip(s: *mut RawIterator, other: RawIterator) -> RawIterator {
-    make_raw_iterator(to_inner(s).zip(other))
+    ffi_guard(|| make_raw_iterator(to_inner(s).zip(other)))
 }
 
 #[no_mangle]
 extern "C" fn iter_map(s: *mut RawIterator, f: extern "C" fn(*mut c_void)) -> RawIterator {
-    make_raw_iterator(to_inner(s).map(move |a| f(a)))
+    ffi_guard(|| make_raw_iterator(to_inner(s).map(move |a| f(a))))
 }
 
 #[no_mangle]
 extern "C" fn iter_for_each(s: *mut RawIterator, f: extern "C" fn(*mut c_void)) {
-    to_inner(s).for_each(move |a| f(a));
+    ffi_guard(|| {
+        to_inner(s).for_each(move |a| f(a));
+    })
 }
 
+/// The items predicate rejects are freed as they're passed.
 #[no_mangle]
 extern "C" fn iter_filter(
     s: *mut RawIterator,
     predicate: extern "C" fn(*mut c_void) -> bool,
 ) -> RawIterator {
-    make_raw_iterator(to_inner(s).filter(move |a| predicate(*a)))
+    ffi_guard(|| {
+        let inner = *to_inner(s);
+        make_raw_iterator(inner.filter(move |a| {
+            let keep = predicate(*a);
+            if !keep {
+                inner.release_item(*a);
+            }
+            keep
+        }))
+    })
 }
 #[no_mangle]
 extern "C" fn iter_filter_map(
     s: *mut RawIterator,
     f: extern "C" fn(*mut c_void) -> *mut RawIterator,
 ) -> RawIterator {
-    make_raw_iterator(
-        to_inner(s).filter_map(move |a| match unsafe { f(a).as_ref() } {
-            Some(mut a) => Some(*a),
-            None => None,
-        }),
-    )
+    ffi_guard(|| {
+        make_raw_iterator(to_inner(s).filter_map(move |a| unsafe { f(a).as_ref() }.copied()))
+    })
 }
 #[no_mangle]
 extern "C" fn iter_enumerate(s: *mut RawIterator) -> RawIterator {
-    make_raw_iterator(to_inner(s).enumerate())
+    ffi_guard(|| make_raw_iterator(to_inner(s).enumerate()))
 }
 #[no_mangle]
 extern "C" fn iter_peekable(s: *mut RawIterator) -> RawIterator {
-    make_raw_iterator(to_inner(s).peekable())
+    ffi_guard(|| make_raw_iterator(to_inner(s).peekable()))
 }
+/// The items skipped are freed as they're passed.
 #[no_mangle]
 extern "C" fn iter_skip_while(
     s: *mut RawIterator,
     predicate: extern "C" fn(*mut c_void) -> bool,
 ) -> RawIterator {
-    make_raw_iterator(to_inner(s).skip_while(move |a| predicate(*a)))
-}
+    ffi_guard(|| {
+        let inner = *to_inner(s);
+        make_raw_iterator(inner.skip_while(move |a| {
+            let skip = predicate(*a);
+            if skip {
+                inner.release_item(*a);
+            }
+            skip
+        }))
+    })
+}
+/// The item that ends it is freed.
 #[no_mangle]
 extern "C" fn iter_take_while(
     s: *mut RawIterator,
     predicate: extern "C" fn(*mut c_void) -> bool,
 ) -> RawIterator {
-    make_raw_iterator(to_inner(s).take_while(move |a| predicate(*a)))
+    ffi_guard(|| {
+        let inner = *to_inner(s);
+        make_raw_iterator(inner.take_while(move |a| {
+            let take = predicate(*a);
+            if !take {
+                inner.release_item(*a);
+            }
+            take
+        }))
+    })
 }
 #[no_mangle]
 extern "C" fn iter_map_while(
     s: *mut RawIterator,
     predicate: extern "C" fn(*mut c_void) -> *mut RawIterator,
 ) -> RawIterator {
-    make_raw_iterator(to_inner(s).map_while(move |a: *mut c_void| {
-        match unsafe { predicate(a).as_ref() } {
-            Some(a) => Some(*a),
-            None => None,
-        }
-    }))
+    ffi_guard(|| {
+        make_raw_iterator(
+            to_inner(s).map_while(move |a: *mut c_void| unsafe { predicate(a).as_ref() }.copied()),
+        )
+    })
 }
+/// The items skipped are freed as they're passed.
 #[no_mangle]
 extern "C" fn iter_skip(s: *mut RawIterator, n: usize) -> RawIterator {
-    make_raw_iterator(to_inner(s).skip(n))
+    ffi_guard(|| {
+        let inner = *to_inner(s);
+        let mut left = n;
+        make_raw_iterator(inner.skip_while(move |a| {
+            if left == 0 {
+                return false;
+            }
+            left -= 1;
+            inner.release_item(*a);
+            true
+        }))
+    })
 }
 #[no_mangle]
 extern "C" fn iter_take(s: *mut RawIterator, n: usize) -> RawIterator {
-    make_raw_iterator(to_inner(s).take(n))
+    ffi_guard(|| make_raw_iterator(to_inner(s).take(n)))
 }
 #[no_mangle]
 extern "C" fn iter_scan(
@@ -204,32 +329,51 @@ extern "C" fn iter_scan(
     initial_state: *mut c_void,
     f: extern "C" fn(*mut c_void, *mut c_void) -> *mut c_void,
 ) -> RawIterator {
-    make_raw_iterator(to_inner(s).scan(initial_state, move |a, b| unsafe { f(*a, b).as_ref() }))
+    ffi_guard(|| {
+        make_raw_iterator(to_inner(s).scan(initial_state, move |a, b| unsafe { f(*a, b).as_ref() }))
+    })
 }
 #[no_mangle]
 extern "C" fn iter_flat_map(
     s: *mut RawIterator,
     f: extern "C" fn(*mut c_void) -> RawIterator,
 ) -> RawIterator {
-    make_raw_iterator(to_inner(s).flat_map(move |a| f(a)))
+    ffi_guard(|| make_raw_iterator(to_inner(s).flat_map(move |a| f(a))))
 }
 #[no_mangle]
 extern "C" fn iter_fuse(s: *mut RawIterator) -> RawIterator {
-    make_raw_iterator(to_inner(s).fuse())
+    ffi_guard(|| make_raw_iterator(to_inner(s).fuse()))
 }
 #[no_mangle]
 extern "C" fn iter_inspect(s: *mut RawIterator, f: extern "C" fn(*mut c_void)) -> RawIterator {
-    make_raw_iterator(to_inner(s).inspect(move |a| f(*a)))
+    ffi_guard(|| make_raw_iterator(to_inner(s).inspect(move |a| f(*a))))
 }
 #[no_mangle]
 extern "C" fn iter_by_ref(s: *mut RawIterator) -> RawIterator {
-    make_raw_iterator(to_inner_mut(s).by_ref())
+    ffi_guard(|| make_raw_iterator(to_inner_mut(s).by_ref()))
 }
+/// Collects the remaining items into an array, writing its length to size.
+///
+/// The array has to be freed with iter_collect_free, and the items in it with iter_item_free.
 #[no_mangle]
 extern "C" fn iter_collect(s: *mut RawIterator, size: &mut usize) -> *mut *mut c_void {
-    let mut v: Vec<*mut c_void> = to_inner_mut(s).collect();
-    *size = v.len();
-    Box::leak(Box::new(v.as_mut_slice())).as_mut_ptr()
+    ffi_guard(|| {
+        let v: Vec<*mut c_void> = to_inner_mut(s).collect();
+        *size = v.len();
+        Box::leak(v.into_boxed_slice()).as_mut_ptr()
+    })
+}
+
+/// Frees an array returned by iter_collect, but not the items in it. size has to be the length that was given back with it.
+#[no_mangle]
+extern "C" fn iter_collect_free(items: *mut *mut c_void, size: usize) {
+    ffi_guard(|| {
+        if !items.is_null() {
+            std::mem::drop(unsafe {
+                Box::from_raw(std::ptr::slice_from_raw_parts_mut(items, size))
+            });
+        }
+    })
 }
 
 #[no_mangle]
@@ -238,69 +382,124 @@ extern "C" fn iter_fold(
     init: *mut c_void,
     f: extern "C" fn(*mut c_void, *mut c_void) -> *mut c_void,
 ) -> *mut c_void {
-    to_inner(s).fold(init, |a, b| f(a, b))
+    ffi_guard(|| to_inner(s).fold(init, |a, b| f(a, b)))
 }
 #[no_mangle]
 extern "C" fn iter_reduce(
     s: *mut RawIterator,
     f: extern "C" fn(*mut c_void, *mut c_void) -> *mut c_void,
 ) -> *mut c_void {
-    match to_inner(s).reduce(|s, a| f(s, a)) {
+    ffi_guard(|| match to_inner(s).reduce(|s, a| f(s, a)) {
         Some(a) => a,
         None => std::ptr::null_mut(),
-    }
+    })
 }
+/// Every item f is called with is freed afterwards.
 #[no_mangle]
 extern "C" fn iter_all(s: *mut RawIterator, f: extern "C" fn(*mut c_void) -> bool) -> bool {
-    to_inner_mut(s).all(|a| f(a))
-}
+    ffi_guard(|| {
+        let inner = to_inner_mut(s);
+        let owner = *inner;
+        inner.all(|a| {
+            let r = f(a);
+            owner.release_item(a);
+            r
+        })
+    })
+}
+/// Every item f is called with is freed afterwards.
 #[no_mangle]
 extern "C" fn iter_any(s: *mut RawIterator, f: extern "C" fn(*mut c_void) -> bool) -> bool {
-    to_inner_mut(s).any(|a| f(a))
-}
+    ffi_guard(|| {
+        let inner = to_inner_mut(s);
+        let owner = *inner;
+        inner.any(|a| {
+            let r = f(a);
+            owner.release_item(a);
+            r
+        })
+    })
+}
+/// Returns the first item predicate accepts, which is freed like any other item of the iterator. The items it
+/// rejects are freed along the way.
 #[no_mangle]
 extern "C" fn iter_find(
     s: *mut RawIterator,
     predicate: extern "C" fn(*mut c_void) -> bool,
 ) -> *mut c_void {
-    match to_inner_mut(s).find(|p| predicate(*p)) {
-        Some(a) => a,
-        None => std::ptr::null_mut(),
-    }
+    ffi_guard(|| {
+        let inner = to_inner_mut(s);
+        while let Some(a) = inner.next() {
+            if predicate(a) {
+                return a;
+            }
+            inner.release_item(a);
+        }
+        std::ptr::null_mut()
+    })
 }
 #[no_mangle]
 extern "C" fn iter_find_map(
     s: *mut RawIterator,
     f: extern "C" fn(*mut c_void) -> *mut c_void,
 ) -> *mut c_void {
-    match to_inner_mut(s).find_map(|a| unsafe { f(a).as_mut() }) {
-        Some(a) => a as *mut c_void,
-        None => std::ptr::null_mut(),
-    }
+    ffi_guard(
+        || match to_inner_mut(s).find_map(|a| unsafe { f(a).as_mut() }) {
+            Some(a) => a as *mut c_void,
+            None => std::ptr::null_mut(),
+        },
+    )
 }
+/// The result has to be freed with iter_usize_free. Every item predicate is called with is freed afterwards.
 #[no_mangle]
 extern "C" fn iter_position(
     s: *mut RawIterator,
     predicate: extern "C" fn(*mut c_void) -> bool,
 ) -> *mut usize {
-    match to_inner_mut(s).position(|a| predicate(a)) {
+    let position = |inner: &mut RawIterator| {
+        let mut i = 0;
+        while let Some(a) = inner.next() {
+            let found = predicate(a);
+            inner.release_item(a);
+            if found {
+                return Some(i);
+            }
+            i += 1;
+        }
+        None
+    };
+    ffi_guard(|| match position(to_inner_mut(s)) {
         Some(a) => Box::leak(Box::new(a)),
         None => std::ptr::null_mut(),
+    })
+}
+/// Keeps whichever item better returns true for over the one kept so far, freeing the other.
+fn pick(s: *mut RawIterator, better: fn(*mut c_void, *mut c_void) -> bool) -> *mut c_void {
+    let inner = to_inner_mut(s);
+    let mut kept = match inner.next() {
+        Some(a) => a,
+        None => return std::ptr::null_mut(),
+    };
+    while let Some(a) = inner.next() {
+        match better(a, kept) {
+            true => {
+                inner.release_item(kept);
+                kept = a;
+            }
+            false => inner.release_item(a),
+        }
     }
+    kept
 }
+/// Returns the item with the highest address, which is freed like any other item of the iterator. The rest are freed.
 #[no_mangle]
 extern "C" fn iter_max(s: *mut RawIterator) -> *mut c_void {
-    match to_inner(s).max() {
-        Some(a) => a,
-        None => std::ptr::null_mut(),
-    }
+    ffi_guard(|| pick(s, |a, kept| a >= kept))
 }
+/// Returns the item with the lowest address, which is freed like any other item of the iterator. The rest are freed.
 #[no_mangle]
 extern "C" fn iter_min(s: *mut RawIterator) -> *mut c_void {
-    match to_inner(s).min() {
-        Some(a) => a,
-        None => std::ptr::null_mut(),
-    }
+    ffi_guard(|| pick(s, |a, kept| a < kept))
 }
 /*#[no_mangle]
 extern "C" fn iter_max_by_key(
@@ -348,38 +547,243 @@ extern "C" fn iter_sum(s: *mut RawIterator, size: &mut usize) -> *mut *mut c_voi
 
 }*/
 
+/// Compares the items of both iterators by address, lexicographically, freeing every item it looks at.
+fn compare(s: *mut RawIterator, mut other: RawIterator) -> std::cmp::Ordering {
+    let inner = to_inner_mut(s);
+    loop {
+        let (a, b) = (inner.next(), other.next());
+        let order = match (a, b) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (Some(a), Some(b)) => a.cmp(&b),
+        };
+        a.inspect(|&a| inner.release_item(a));
+        b.inspect(|&b| other.release_item(b));
+        if order != std::cmp::Ordering::Equal {
+            return order;
+        }
+    }
+}
+
 #[no_mangle]
 extern "C" fn iter_cmp(s: *mut RawIterator, other: RawIterator) -> Ordering {
-    Ordering::from_std(to_inner(s).cmp(other))
+    ffi_guard(|| Ordering::from_std(compare(s, other)))
 }
+/// The result has to be freed with iter_ordering_free.
 #[no_mangle]
 extern "C" fn iter_partial_cmp(s: *mut RawIterator, other: RawIterator) -> *mut Ordering {
-    match to_inner(s).partial_cmp(other) {
-        Some(a) => Box::leak(Box::new(Ordering::from_std(a))),
-        None => std::ptr::null_mut(),
-    }
+    ffi_guard(|| Box::leak(Box::new(Ordering::from_std(compare(s, other)))) as *mut Ordering)
 }
 #[no_mangle]
 extern "C" fn iter_eq(s: *mut RawIterator, other: RawIterator) -> bool {
-    to_inner(s).eq(other)
+    ffi_guard(|| compare(s, other).is_eq())
 }
 #[no_mangle]
 extern "C" fn iter_ne(s: *mut RawIterator, other: RawIterator) -> bool {
-    to_inner(s).ne(other)
+    ffi_guard(|| compare(s, other).is_ne())
 }
 #[no_mangle]
 extern "C" fn iter_lt(s: *mut RawIterator, other: RawIterator) -> bool {
-    to_inner(s).lt(other)
+    ffi_guard(|| compare(s, other).is_lt())
 }
 #[no_mangle]
 extern "C" fn iter_le(s: *mut RawIterator, other: RawIterator) -> bool {
-    to_inner(s).le(other)
+    ffi_guard(|| compare(s, other).is_le())
 }
 #[no_mangle]
 extern "C" fn iter_gt(s: *mut RawIterator, other: RawIterator) -> bool {
-    to_inner(s).gt(other)
+    ffi_guard(|| compare(s, other).is_gt())
 }
 #[no_mangle]
 extern "C" fn iter_ge(s: *mut RawIterator, other: RawIterator) -> bool {
-    to_inner(s).ge(other)
+    ffi_guard(|| compare(s, other).is_ge())
+}
+
+/// Frees the iterator. Doing this to an iterator twice, or to a copy of one that's already been freed, is undefined.
+///
+/// Items that came out of it have to be freed with iter_item_free first.
+#[no_mangle]
+extern "C" fn iter_free(s: *mut RawIterator) {
+    ffi_guard(|| {
+        if let Some(s) = unsafe { s.as_mut() } {
+            if !s.__s.is_null() {
+                std::mem::drop(unsafe { Box::from_raw(s.__s) });
+                s.__s = std::ptr::null_mut();
+            }
+        }
+    })
+}
+
+/// Frees an item returned by iter_next, or by anything else that hands out the iterator's items. Does nothing if item is NULL.
+///
+/// s has to be the iterator the item came from, and it has to not have been freed yet.
+#[no_mangle]
+extern "C" fn iter_item_free(s: *mut RawIterator, item: *mut c_void) {
+    ffi_guard(|| to_inner(s).release_item(item))
+}
+
+/// Frees the number returned by iter_position or in the upper bound of iter_size_hint.
+#[no_mangle]
+extern "C" fn iter_usize_free(n: *mut usize) {
+    ffi_guard(|| {
+        if !n.is_null() {
+            std::mem::drop(unsafe { Box::from_raw(n) });
+        }
+    })
+}
+
+/// Frees the ordering returned by iter_partial_cmp.
+#[no_mangle]
+extern "C" fn iter_ordering_free(o: *mut Ordering) {
+    ffi_guard(|| {
+        if !o.is_null() {
+            std::mem::drop(unsafe { Box::from_raw(o) });
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    };
+
+    use super::*;
+    use crate::thin::free_boxed;
+
+    #[derive(Default)]
+    struct Counts {
+        made: AtomicUsize,
+        freed: AtomicUsize,
+    }
+
+    /// Yields n boxed numbers counting down to 0, and counts how many it hands out and gets back.
+    struct Counting {
+        left: u32,
+        counts: Arc<Counts>,
+    }
+
+    impl ThinIterator for Counting {
+        fn next(&mut self) -> *mut c_void {
+            if self.left == 0 {
+                return std::ptr::null_mut();
+            }
+            self.left -= 1;
+            self.counts.made.fetch_add(1, SeqCst);
+            Box::leak(Box::new(self.left)) as *mut u32 as *mut c_void
+        }
+        unsafe fn free_item(&self, item: *mut c_void) {
+            self.counts.freed.fetch_add(1, SeqCst);
+            free_boxed::<u32>(item)
+        }
+    }
+
+    fn counting(n: u32) -> (RawIterator, Arc<Counts>) {
+        let counts = Arc::new(Counts::default());
+        let iter = make_raw_iterator(Counting {
+            left: n,
+            counts: counts.clone(),
+        });
+        (iter, counts)
+    }
+
+    /// Frees the rest of the iterator, then checks that every item it made was freed exactly once.
+    fn check(mut iter: RawIterator, counts: &Counts) {
+        while let Some(a) = iter.next() {
+            iter.release_item(a);
+        }
+        iter_free(&mut iter);
+        assert!(counts.made.load(SeqCst) > 0);
+        assert_eq!(counts.made.load(SeqCst), counts.freed.load(SeqCst));
+    }
+
+    /// Drains an adaptor built on base, freeing each of its items and the item of base it wraps.
+    fn drain(mut adaptor: RawIterator, base: &mut RawIterator) -> usize {
+        let mut n = 0;
+        while let Some(a) = adaptor.next() {
+            base.release_item(unsafe { *(a as *mut *mut c_void) });
+            adaptor.release_item(a);
+            n += 1;
+        }
+        iter_free(&mut adaptor);
+        n
+    }
+
+    extern "C" fn even(a: *mut c_void) -> bool {
+        (unsafe { *(a as *mut u32) }) % 2 == 0
+    }
+
+    extern "C" fn above_3(a: *mut c_void) -> bool {
+        (unsafe { *(a as *mut u32) }) > 3
+    }
+
+    #[test]
+    fn consumers_free_what_they_look_at() {
+        let (mut it, counts) = counting(10);
+        assert_eq!(iter_count(&mut it), 10);
+        check(it, &counts);
+
+        let (mut it, counts) = counting(10);
+        assert!(!iter_all(&mut it, above_3));
+        check(it, &counts);
+
+        let (mut it, counts) = counting(10);
+        assert!(iter_any(&mut it, even));
+        check(it, &counts);
+
+        let (mut it, counts) = counting(10);
+        let p = iter_position(&mut it, above_3);
+        assert_eq!(unsafe { *p }, 0);
+        iter_usize_free(p);
+        check(it, &counts);
+
+        for f in [iter_max, iter_min] {
+            let (mut it, counts) = counting(10);
+            let a = f(&mut it);
+            assert!(!a.is_null());
+            iter_item_free(&mut it, a);
+            check(it, &counts);
+        }
+    }
+
+    #[test]
+    fn comparisons_free_both_sides() {
+        for f in [iter_eq, iter_ne, iter_lt, iter_le, iter_gt, iter_ge] {
+            let (mut a, counts_a) = counting(4);
+            let (b, counts_b) = counting(6);
+            f(&mut a, b);
+            check(a, &counts_a);
+            check(b, &counts_b);
+        }
+        let (mut a, counts_a) = counting(5);
+        let (b, counts_b) = counting(5);
+        iter_cmp(&mut a, b);
+        check(a, &counts_a);
+        check(b, &counts_b);
+    }
+
+    #[test]
+    fn adaptors_free_what_they_drop() {
+        let (mut base, counts) = counting(10);
+        assert_eq!(drain(iter_filter(&mut base, even), &mut base), 5);
+        check(base, &counts);
+
+        let (mut base, counts) = counting(10);
+        assert_eq!(drain(iter_step_by(&mut base, 3), &mut base), 4);
+        check(base, &counts);
+
+        let (mut base, counts) = counting(10);
+        assert_eq!(drain(iter_skip(&mut base, 4), &mut base), 6);
+        check(base, &counts);
+
+        let (mut base, counts) = counting(10);
+        assert_eq!(drain(iter_skip_while(&mut base, above_3), &mut base), 4);
+        check(base, &counts);
+
+        let (mut base, counts) = counting(10);
+        assert_eq!(drain(iter_take_while(&mut base, above_3), &mut base), 6);
+        check(base, &counts);
+    }
 }
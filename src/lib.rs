@@ -3,23 +3,30 @@
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
 #![allow(unused_unsafe)]
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
 
 use std::{
-    ffi::{c_char, CStr, CString},
+    ffi::{c_char, CStr},
     io::ErrorKind,
     os::raw::c_void,
+    path::PathBuf,
 };
 
+mod error;
+mod guard;
 mod iter;
 mod thin;
 
-use image::{EncodableLayout, GenericImageView, ImageError};
+use error::set_last_error;
+use guard::{ffi_guard, PanicFallback};
+use image::{EncodableLayout, GenericImageView, ImageDecoder, ImageError};
 use iter::{make_raw_iterator, RawIterator};
 
 /// Struct that contains function pointers that correspond to both Rust's Write trait and it's Seek trait.
 /// The idea here is that you put the pointer for your custom own struct in the user_data field,
 /// then pass your own functions which will then access that user data.
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct RustWriter {
     pub user_data: *mut c_void,
     pub write_fn: extern "C" fn(ud: *mut c_void, buf: *const u8, buf_size: usize) -> usize,
@@ -37,27 +44,46 @@ impl std::io::Write for RustWriter {
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        Ok((self.flush_fn)(self.user_data))
+        (self.flush_fn)(self.user_data);
+        Ok(())
     }
 }
 
 impl std::io::Seek for RustWriter {
     fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
-        let raw_sp = match pos {
-            std::io::SeekFrom::Start(s) => SeekFrom {
-                ty: SeekType::SEEK_FROM_START,
-                val: SeekUnion { start: s },
-            },
-            std::io::SeekFrom::End(e) => SeekFrom {
-                ty: SeekType::SEEK_FROM_END,
-                val: SeekUnion { end: e },
-            },
-            std::io::SeekFrom::Current(c) => SeekFrom {
-                ty: SeekType::SEEK_FROM_CURRENT,
-                val: SeekUnion { current: c },
-            },
-        };
-        Ok((self.seek_fn)(self.user_data, raw_sp))
+        Ok((self.seek_fn)(self.user_data, pos.into()))
+    }
+}
+
+/// Struct that contains function pointers that correspond to both Rust's Read trait and it's Seek trait.
+/// Like RustWriter, you put the pointer for your custom own struct in the user_data field,
+/// then pass your own functions which will then access that user data.
+///
+/// read_fn should copy at most buf_size bytes into buf and return how many were copied, returning 0 once there is nothing left to read
+/// and -1 if reading failed. A failed read is reported as IMAGE_ERROR_IO_OTHER.
+///
+/// Only Read and Seek are implemented, not BufRead. BufRead needs a buffer owned by the reader, which a struct
+/// that C fills in with nothing but callbacks has no room for, so the functions taking a RustReader wrap it in a
+/// std::io::BufReader instead. That way read_fn is called with reasonably large chunks rather than a byte at a time.
+#[repr(C)]
+pub struct RustReader {
+    pub user_data: *mut c_void,
+    pub read_fn: extern "C" fn(ud: *mut c_void, buf: *mut u8, buf_size: usize) -> isize,
+    pub seek_fn: extern "C" fn(ud: *mut c_void, pos: SeekFrom) -> u64,
+}
+
+impl std::io::Read for RustReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match (self.read_fn)(self.user_data, buf.as_mut_ptr(), buf.len()) {
+            n if n < 0 => Err(std::io::Error::other("read_fn reported an error")),
+            n => Ok((n as usize).min(buf.len())),
+        }
+    }
+}
+
+impl std::io::Seek for RustReader {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        Ok((self.seek_fn)(self.user_data, pos.into()))
     }
 }
 
@@ -85,15 +111,13 @@ impl image::ImageEncoder for &mut ImageEncoder {
         height: u32,
         color_type: image::ExtendedColorType,
     ) -> image::ImageResult<()> {
-        let c = color_type;
-        let color_type: u16 = unsafe { std::mem::transmute(c) };
         (self.writeFn)(
             self.user_data,
             buf.as_ptr(),
             buf.len(),
             width,
             height,
-            unsafe { std::mem::transmute(color_type as u32) },
+            ExtendedColorType::from_real(color_type),
         );
         Ok(())
     }
@@ -107,6 +131,25 @@ pub struct SeekFrom {
     val: SeekUnion,
 }
 
+impl From<std::io::SeekFrom> for SeekFrom {
+    fn from(pos: std::io::SeekFrom) -> Self {
+        match pos {
+            std::io::SeekFrom::Start(s) => SeekFrom {
+                ty: SeekType::SEEK_FROM_START,
+                val: SeekUnion { start: s },
+            },
+            std::io::SeekFrom::End(e) => SeekFrom {
+                ty: SeekType::SEEK_FROM_END,
+                val: SeekUnion { end: e },
+            },
+            std::io::SeekFrom::Current(c) => SeekFrom {
+                ty: SeekType::SEEK_FROM_CURRENT,
+                val: SeekUnion { current: c },
+            },
+        }
+    }
+}
+
 /// Union used for the SeekFrom struct
 #[repr(C)]
 pub union SeekUnion {
@@ -119,6 +162,7 @@ pub union SeekUnion {
 }
 
 #[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum ErrorType {
     IMAGE_ERROR_NONE,
     IMAGE_ERROR_DECODING,
@@ -154,10 +198,19 @@ pub enum ErrorType {
     IMAGE_ERROR_IO_OUT_OF_MEMORY,
     IMAGE_ERROR_IO_OTHER,
     IMAGE_ERROR_UNKNOWN,
+    /// The library panicked while handling the call. This is a bug in rs_image, the image crate, or the arguments passed in.
+    IMAGE_ERROR_PANIC,
+}
+
+impl PanicFallback for ErrorType {
+    fn panic_fallback() -> Self {
+        ErrorType::IMAGE_ERROR_PANIC
+    }
 }
 
+/// Maps the error onto an ErrorType, keeping its full description around for rs_image_last_error_message.
 fn get_image_error(er: ImageError) -> ErrorType {
-    match er {
+    let code = match &er {
         image::ImageError::Decoding(_) => ErrorType::IMAGE_ERROR_DECODING,
         image::ImageError::Encoding(_) => ErrorType::IMAGE_ERROR_ENCODING,
         image::ImageError::Parameter(a) => match a.kind() {
@@ -173,7 +226,7 @@ fn get_image_error(er: ImageError) -> ErrorType {
             image::error::ParameterErrorKind::NoMoreData => {
                 ErrorType::IMAGE_ERROR_PARAMETER_NO_MORE_DATA
             }
-            _ => todo!(),
+            _ => ErrorType::IMAGE_ERROR_PARAMETER_MALFORMED,
         },
         image::ImageError::Limits(a) => match a.kind() {
             image::error::LimitErrorKind::DimensionError => ErrorType::IMAGE_ERROR_DIMENSION_ERROR,
@@ -183,7 +236,7 @@ fn get_image_error(er: ImageError) -> ErrorType {
             image::error::LimitErrorKind::Unsupported { .. } => {
                 ErrorType::IMAGE_ERROR_LIMITS_UNSUPPORTED
             }
-            _ => todo!(),
+            _ => ErrorType::IMAGE_ERROR_LIMITS_UNSUPPORTED,
         },
         image::ImageError::Unsupported(a) => match a.kind() {
             image::error::UnsupportedErrorKind::Color(_) => {
@@ -195,7 +248,7 @@ fn get_image_error(er: ImageError) -> ErrorType {
             image::error::UnsupportedErrorKind::GenericFeature(_) => {
                 ErrorType::IMAGE_ERROR_UNSUPPORTED_OTHER
             }
-            _ => todo!(),
+            _ => ErrorType::IMAGE_ERROR_UNSUPPORTED_OTHER,
         },
         image::ImageError::IoError(a) => match a.kind() {
             ErrorKind::NotFound => ErrorType::IMAGE_ERROR_IO_NOT_FOUND,
@@ -220,10 +273,12 @@ fn get_image_error(er: ImageError) -> ErrorType {
             ErrorKind::Other => ErrorType::IMAGE_ERROR_IO_OTHER,
             _ => ErrorType::IMAGE_ERROR_UNKNOWN, // This means Rust added more errors then the last time this was updated.
         },
-    }
+    };
+    set_last_error(code, er.to_string())
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub enum FilterType {
     FILTER_TYPE_NEAREST,
     FILTER_TYPE_TRIANGLE,
@@ -250,7 +305,31 @@ pub enum ImageFormat {
     IMAGE_FORMAT_AVIF,
     IMAGE_FORMAT_QOI,
 }
+
+impl ImageFormat {
+    fn from_real(real: image::ImageFormat) -> Option<Self> {
+        Some(match real {
+            image::ImageFormat::Png => ImageFormat::IMAGE_FORMAT_PNG,
+            image::ImageFormat::Jpeg => ImageFormat::IMAGE_FORMAT_JPEG,
+            image::ImageFormat::Gif => ImageFormat::IMAGE_FORMAT_GIF,
+            image::ImageFormat::WebP => ImageFormat::IMAGE_FORMAT_WEBP,
+            image::ImageFormat::Pnm => ImageFormat::IMAGE_FORMAT_PNM,
+            image::ImageFormat::Tiff => ImageFormat::IMAGE_FORMAT_TIFF,
+            image::ImageFormat::Tga => ImageFormat::IMAGE_FORMAT_TGA,
+            image::ImageFormat::Dds => ImageFormat::IMAGE_FORMAT_DDS,
+            image::ImageFormat::Bmp => ImageFormat::IMAGE_FORMAT_BMP,
+            image::ImageFormat::Ico => ImageFormat::IMAGE_FORMAT_ICO,
+            image::ImageFormat::Hdr => ImageFormat::IMAGE_FORMAT_HDR,
+            image::ImageFormat::OpenExr => ImageFormat::IMAGE_FORMAT_OPENEXR,
+            image::ImageFormat::Farbfeld => ImageFormat::IMAGE_FORMAT_FARBFELD,
+            image::ImageFormat::Avif => ImageFormat::IMAGE_FORMAT_AVIF,
+            image::ImageFormat::Qoi => ImageFormat::IMAGE_FORMAT_QOI,
+            _ => return None,
+        })
+    }
+}
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub enum ColorType {
     COLOR_TYPE_L8,
     COLOR_TYPE_LA8,
@@ -264,6 +343,39 @@ pub enum ColorType {
     COLOR_TYPE_RGBA32F,
 }
 
+impl ColorType {
+    fn from_real(real: image::ColorType) -> Self {
+        match real {
+            image::ColorType::L8 => ColorType::COLOR_TYPE_L8,
+            image::ColorType::La8 => ColorType::COLOR_TYPE_LA8,
+            image::ColorType::Rgb8 => ColorType::COLOR_TYPE_RGB8,
+            image::ColorType::Rgba8 => ColorType::COLOR_TYPE_RGBA8,
+            image::ColorType::L16 => ColorType::COLOR_TYPE_L16,
+            image::ColorType::La16 => ColorType::COLOR_TYPE_LA16,
+            image::ColorType::Rgb16 => ColorType::COLOR_TYPE_RGB16,
+            image::ColorType::Rgba16 => ColorType::COLOR_TYPE_RGBA16,
+            image::ColorType::Rgb32F => ColorType::COLOR_TYPE_RGB32F,
+            image::ColorType::Rgba32F => ColorType::COLOR_TYPE_RGBA32F,
+            // DynamicImage can't hold anything else.
+            _ => unreachable!(),
+        }
+    }
+    fn to_real(self) -> image::ColorType {
+        match self {
+            ColorType::COLOR_TYPE_L8 => image::ColorType::L8,
+            ColorType::COLOR_TYPE_LA8 => image::ColorType::La8,
+            ColorType::COLOR_TYPE_RGB8 => image::ColorType::Rgb8,
+            ColorType::COLOR_TYPE_RGBA8 => image::ColorType::Rgba8,
+            ColorType::COLOR_TYPE_L16 => image::ColorType::L16,
+            ColorType::COLOR_TYPE_LA16 => image::ColorType::La16,
+            ColorType::COLOR_TYPE_RGB16 => image::ColorType::Rgb16,
+            ColorType::COLOR_TYPE_RGBA16 => image::ColorType::Rgba16,
+            ColorType::COLOR_TYPE_RGB32F => image::ColorType::Rgb32F,
+            ColorType::COLOR_TYPE_RGBA32F => image::ColorType::Rgba32F,
+        }
+    }
+}
+
 #[repr(C)]
 pub enum ExtendedColorType {
     EXTENDED_COLOR_TYPE_A8,
@@ -292,6 +404,41 @@ pub enum ExtendedColorType {
     EXTENDED_COLOR_TYPE_RGB32F,
     EXTENDED_COLOR_TYPE_RGBA32F,
     EXTENDED_COLOR_TYPE_CMYK8,
+    EXTENDED_COLOR_TYPE_UNKNOWN,
+}
+
+impl ExtendedColorType {
+    fn from_real(real: image::ExtendedColorType) -> Self {
+        match real {
+            image::ExtendedColorType::A8 => ExtendedColorType::EXTENDED_COLOR_TYPE_A8,
+            image::ExtendedColorType::L1 => ExtendedColorType::EXTENDED_COLOR_TYPE_L1,
+            image::ExtendedColorType::La1 => ExtendedColorType::EXTENDED_COLOR_TYPE_LA1,
+            image::ExtendedColorType::Rgb1 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGB1,
+            image::ExtendedColorType::Rgba1 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA1,
+            image::ExtendedColorType::L2 => ExtendedColorType::EXTENDED_COLOR_TYPE_L2,
+            image::ExtendedColorType::La2 => ExtendedColorType::EXTENDED_COLOR_TYPE_LA2,
+            image::ExtendedColorType::Rgb2 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGB2,
+            image::ExtendedColorType::Rgba2 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA2,
+            image::ExtendedColorType::L4 => ExtendedColorType::EXTENDED_COLOR_TYPE_L4,
+            image::ExtendedColorType::La4 => ExtendedColorType::EXTENDED_COLOR_TYPE_LA4,
+            image::ExtendedColorType::Rgb4 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGB4,
+            image::ExtendedColorType::Rgba4 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA4,
+            image::ExtendedColorType::L8 => ExtendedColorType::EXTENDED_COLOR_TYPE_L8,
+            image::ExtendedColorType::La8 => ExtendedColorType::EXTENDED_COLOR_TYPE_LA8,
+            image::ExtendedColorType::Rgb8 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGB8,
+            image::ExtendedColorType::Rgba8 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA8,
+            image::ExtendedColorType::L16 => ExtendedColorType::EXTENDED_COLOR_TYPE_L16,
+            image::ExtendedColorType::La16 => ExtendedColorType::EXTENDED_COLOR_TYPE_LA16,
+            image::ExtendedColorType::Rgb16 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGB16,
+            image::ExtendedColorType::Rgba16 => ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA16,
+            image::ExtendedColorType::Bgr8 => ExtendedColorType::EXTENDED_COLOR_TYPE_BGR8,
+            image::ExtendedColorType::Bgra8 => ExtendedColorType::EXTENDED_COLOR_TYPE_BGRA8,
+            image::ExtendedColorType::Rgb32F => ExtendedColorType::EXTENDED_COLOR_TYPE_RGB32F,
+            image::ExtendedColorType::Rgba32F => ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA32F,
+            image::ExtendedColorType::Cmyk8 => ExtendedColorType::EXTENDED_COLOR_TYPE_CMYK8,
+            _ => ExtendedColorType::EXTENDED_COLOR_TYPE_UNKNOWN,
+        }
+    }
 }
 
 /// Type used for the SeekFrom struct
@@ -305,6 +452,14 @@ pub enum SeekType {
 #[repr(C)]
 pub struct DynamicImage {
     inner: *mut c_void,
+    /// The ICC profile the image was loaded with, as a Box<Vec<u8>>, or NULL if it has none.
+    icc: *mut c_void,
+}
+
+impl Drop for DynamicImage {
+    fn drop(&mut self) {
+        self.set_icc(None);
+    }
 }
 
 impl DynamicImage {
@@ -315,13 +470,25 @@ impl DynamicImage {
         if ptr.is_null() {
             panic!("FATAL: Tried to call method on DynamicImage, which is null");
         }
-        return unsafe { (ptr as *const image::DynamicImage).as_ref().unwrap() };
+        unsafe { (ptr as *const image::DynamicImage).as_ref().unwrap() }
+    }
+    fn icc(&self) -> Option<&[u8]> {
+        unsafe { (self.icc as *const Vec<u8>).as_ref() }.map(|a| a.as_slice())
     }
-    fn to_real_mut(&self) -> &'static mut image::DynamicImage {
+    fn set_icc(&mut self, icc: Option<Vec<u8>>) {
+        if !self.icc.is_null() {
+            std::mem::drop(unsafe { Box::from_raw(self.icc as *mut Vec<u8>) });
+        }
+        self.icc = match icc {
+            Some(a) => Box::into_raw(Box::new(a)) as *mut c_void,
+            None => std::ptr::null_mut(),
+        };
+    }
+    fn to_real_mut(&mut self) -> &'static mut image::DynamicImage {
         assert!(!self.inner.is_null());
         assert!(self.inner.is_aligned());
         let ptr = self.inner;
-        return unsafe { (ptr as *mut image::DynamicImage).as_mut().unwrap() };
+        unsafe { (ptr as *mut image::DynamicImage).as_mut().unwrap() }
     }
 }
 
@@ -343,38 +510,401 @@ macro_rules! unravel_mut {
 
 macro_rules! ravel {
     ($f:block) => {{
-        Box::leak(Box::new(DynamicImage {
-            inner: Box::leak(Box::new($f)) as *mut image::DynamicImage as *mut c_void,
-        }))
+        Box::leak(Box::new($crate::DynamicImage {
+            inner: Box::leak(Box::new($f)) as *mut image::DynamicImage as *mut std::ffi::c_void,
+            icc: std::ptr::null_mut(),
+        })) as *mut $crate::DynamicImage
     }};
 }
 
+// These use the macros above, so they have to be declared after them.
+pub mod alpha;
+pub mod animation;
+pub mod composite;
+pub mod encode;
+pub mod exif;
+pub mod icc;
+pub mod linear;
+pub mod pixel;
+pub mod transform;
+
 #[repr(C)]
 pub struct LoadFromMemoryResult {
     pub res: *mut DynamicImage,
     pub err: ErrorType,
 }
 
+impl PanicFallback for LoadFromMemoryResult {
+    fn panic_fallback() -> Self {
+        LoadFromMemoryResult {
+            res: std::ptr::null_mut(),
+            err: ErrorType::IMAGE_ERROR_PANIC,
+        }
+    }
+}
+
+/// A decoded image along with the ICC profile that was embedded in it.
+struct Decoded {
+    image: image::DynamicImage,
+    icc: Option<Vec<u8>>,
+}
+
+/// Decodes the image the same way ImageReader::decode does, but holds on to its ICC profile,
+/// and if orient is true applies its EXIF orientation.
+fn decode<R: std::io::BufRead + std::io::Seek>(
+    mut reader: image::ImageReader<R>,
+    limits: image::Limits,
+    orient: bool,
+) -> image::ImageResult<Decoded> {
+    reader.limits(limits.clone());
+    let mut decoder = reader.into_decoder()?;
+    let mut limits = limits;
+    limits.reserve(decoder.total_bytes())?;
+    decoder.set_limits(limits)?;
+    // A broken profile isn't worth failing the whole image over.
+    let icc = decoder.icc_profile().ok().flatten();
+    // Neither is a broken EXIF block, which just leaves the image the way it was stored.
+    let orientation = match orient {
+        true => Some(
+            decoder
+                .orientation()
+                .unwrap_or(image::metadata::Orientation::NoTransforms),
+        ),
+        false => None,
+    };
+    let mut image = image::DynamicImage::from_decoder(decoder)?;
+    if let Some(a) = orientation {
+        image.apply_orientation(a);
+    }
+    let icc = icc.filter(|a| icc::matches_color(a, image.color()));
+    Ok(Decoded { image, icc })
+}
+
+fn decode_guessed<R: std::io::BufRead + std::io::Seek>(
+    reader: R,
+    limits: image::Limits,
+    orient: bool,
+) -> image::ImageResult<Decoded> {
+    decode(
+        image::ImageReader::new(reader).with_guessed_format()?,
+        limits,
+        orient,
+    )
+}
+
+impl From<image::ImageResult<Decoded>> for LoadFromMemoryResult {
+    fn from(res: image::ImageResult<Decoded>) -> Self {
+        match res {
+            Ok(a) => {
+                let res = ravel!({ a.image });
+                unsafe { res.as_mut() }.unwrap().set_icc(a.icc);
+                LoadFromMemoryResult {
+                    res,
+                    err: ErrorType::IMAGE_ERROR_NONE,
+                }
+            }
+            Err(er) => LoadFromMemoryResult {
+                res: std::ptr::null_mut(),
+                err: get_image_error(er),
+            },
+        }
+    }
+}
+
+impl From<image::ImageResult<image::DynamicImage>> for LoadFromMemoryResult {
+    fn from(res: image::ImageResult<image::DynamicImage>) -> Self {
+        match res {
+            Ok(a) => LoadFromMemoryResult {
+                res: ravel!({ a }),
+                err: ErrorType::IMAGE_ERROR_NONE,
+            },
+            Err(er) => LoadFromMemoryResult {
+                res: std::ptr::null_mut(),
+                err: get_image_error(er),
+            },
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn dynamic_image_load_from_memory(
     bytes: *mut u8,
     size: usize,
 ) -> LoadFromMemoryResult {
-    assert!(!bytes.is_null());
-    assert!(bytes.is_aligned());
-    assert!(size < isize::MAX as usize);
-    match image::load_from_memory(unsafe { std::slice::from_raw_parts_mut(bytes, size) }) {
-        Ok(a) => LoadFromMemoryResult {
-            res: ravel!({ a }),
-            err: ErrorType::IMAGE_ERROR_NONE,
+    ffi_guard(|| {
+        assert!(!bytes.is_null());
+        assert!(bytes.is_aligned());
+        assert!(size < isize::MAX as usize);
+        let cursor = std::io::Cursor::new(unsafe { std::slice::from_raw_parts(bytes, size) });
+        decode_guessed(cursor, image::Limits::default(), false).into()
+    })
+}
+
+/// Limits the decoder has to stay within, mirroring the image crate's Limits struct.
+/// A value of 0 means that there is no limit for that field.
+#[repr(C)]
+pub struct DecodeLimits {
+    pub max_image_width: u32,
+    pub max_image_height: u32,
+    /// The total amount of bytes the decoder may allocate over the whole decode, including the output image.
+    /// Unlike the other loaders, which keep the image crate's default cap of 512 MiB, 0 leaves this unlimited.
+    pub max_alloc: u64,
+}
+
+impl DecodeLimits {
+    fn to_real(&self) -> image::Limits {
+        let mut limits = image::Limits::no_limits();
+        limits.max_image_width = Some(self.max_image_width).filter(|a| *a != 0);
+        limits.max_image_height = Some(self.max_image_height).filter(|a| *a != 0);
+        limits.max_alloc = Some(self.max_alloc).filter(|a| *a != 0);
+        limits
+    }
+}
+
+/// Same as dynamic_image_load_from_memory, but fails with IMAGE_ERROR_DIMENSION_ERROR or IMAGE_ERROR_INSUFFICIENT_MEMORY
+/// instead of decoding anything bigger than the given limits.
+#[no_mangle]
+pub extern "C" fn dynamic_image_load_from_memory_with_limits(
+    bytes: *mut u8,
+    size: usize,
+    limits: DecodeLimits,
+) -> LoadFromMemoryResult {
+    ffi_guard(|| {
+        assert!(!bytes.is_null());
+        assert!(bytes.is_aligned());
+        assert!(size < isize::MAX as usize);
+        let cursor = std::io::Cursor::new(unsafe { std::slice::from_raw_parts(bytes, size) });
+        decode_guessed(cursor, limits.to_real(), false).into()
+    })
+}
+
+/// Decodes an image by pulling bytes through the given RustReader instead of a buffer in memory.
+/// The format is guessed from the first few bytes, so seek_fn has to be able to go back to the start.
+#[no_mangle]
+pub extern "C" fn dynamic_image_load_from_reader(r: *mut RustReader) -> LoadFromMemoryResult {
+    ffi_guard(|| {
+        assert!(!r.is_null());
+        assert!(r.is_aligned());
+        let reader = std::io::BufReader::new(unsafe { r.as_mut() }.unwrap());
+        decode_guessed(reader, image::Limits::default(), false).into()
+    })
+}
+
+fn invalid_path() -> ErrorType {
+    set_last_error(
+        ErrorType::IMAGE_ERROR_IO_INVALID_INPUT,
+        "path is not valid UTF-8",
+    )
+}
+
+/// Turns a C string into a path without requiring it to be valid UTF-8.
+/// Windows paths aren't arbitrary bytes, so there they still have to be UTF-8.
+fn path_from_ptr(path: *const c_char) -> Option<PathBuf> {
+    assert!(!path.is_null());
+    let bytes = unsafe { CStr::from_ptr(path) }.to_bytes();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        std::str::from_utf8(bytes).ok().map(PathBuf::from)
+    }
+}
+
+/// Opens and decodes the image at the given path, guessing the format from its contents and falling back to the
+/// extension, so a file with the wrong extension or none at all still opens.
+#[no_mangle]
+pub extern "C" fn dynamic_image_open(path: *const c_char) -> LoadFromMemoryResult {
+    ffi_guard(|| match path_from_ptr(path) {
+        Some(p) => match image::ImageReader::open(p).and_then(|a| a.with_guessed_format()) {
+            Ok(a) => decode(a, image::Limits::default(), false).into(),
+            Err(er) => LoadFromMemoryResult {
+                res: std::ptr::null_mut(),
+                err: get_image_error(ImageError::IoError(er)),
+            },
         },
-        Err(er) => LoadFromMemoryResult {
+        None => LoadFromMemoryResult {
             res: std::ptr::null_mut(),
-            err: get_image_error(er),
+            err: invalid_path(),
         },
+    })
+}
+
+/// Opens and decodes the image at the given path as the given format, regardless of what the extension says.
+#[no_mangle]
+pub extern "C" fn dynamic_image_open_with_format(
+    path: *const c_char,
+    format: ImageFormat,
+) -> LoadFromMemoryResult {
+    ffi_guard(|| {
+        let format = unsafe { std::mem::transmute::<u8, image::ImageFormat>(format as u8) };
+        let Some(p) = path_from_ptr(path) else {
+            return LoadFromMemoryResult {
+                res: std::ptr::null_mut(),
+                err: invalid_path(),
+            };
+        };
+        match image::ImageReader::open(p) {
+            Ok(mut a) => {
+                a.set_format(format);
+                decode(a, image::Limits::default(), false).into()
+            }
+            Err(er) => LoadFromMemoryResult {
+                res: std::ptr::null_mut(),
+                err: get_image_error(ImageError::IoError(er)),
+            },
+        }
+    })
+}
+
+/// Creates a new image with every pixel set to zero.
+#[no_mangle]
+pub extern "C" fn dynamic_image_new(
+    width: u32,
+    height: u32,
+    color: ColorType,
+) -> *mut DynamicImage {
+    ffi_guard(|| ravel!({ image::DynamicImage::new(width, height, color.to_real()) }))
+}
+
+/// Packs the rows of a buffer that might have padding at the end of each row into a Vec with none.
+fn pack_rows(data: &[u8], row_len: usize, stride: usize, height: usize) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(row_len * height);
+    for y in 0..height {
+        packed.extend_from_slice(&data[y * stride..y * stride + row_len]);
+    }
+    packed
+}
+
+fn samples_from_ne_bytes<T, const N: usize>(bytes: &[u8], f: fn([u8; N]) -> T) -> Vec<T> {
+    bytes
+        .chunks_exact(N)
+        .map(|a| f(a.try_into().unwrap()))
+        .collect()
+}
+
+/// Makes an image out of tightly packed pixels, reading 16-bit and f32 samples in native byte order.
+fn image_from_packed(
+    width: u32,
+    height: u32,
+    color: image::ColorType,
+    packed: Vec<u8>,
+) -> Option<image::DynamicImage> {
+    match color {
+        image::ColorType::L8 => {
+            image::ImageBuffer::from_raw(width, height, packed).map(image::DynamicImage::ImageLuma8)
+        }
+        image::ColorType::La8 => image::ImageBuffer::from_raw(width, height, packed)
+            .map(image::DynamicImage::ImageLumaA8),
+        image::ColorType::Rgb8 => {
+            image::ImageBuffer::from_raw(width, height, packed).map(image::DynamicImage::ImageRgb8)
+        }
+        image::ColorType::Rgba8 => {
+            image::ImageBuffer::from_raw(width, height, packed).map(image::DynamicImage::ImageRgba8)
+        }
+        image::ColorType::L16 => image::ImageBuffer::from_raw(
+            width,
+            height,
+            samples_from_ne_bytes(&packed, u16::from_ne_bytes),
+        )
+        .map(image::DynamicImage::ImageLuma16),
+        image::ColorType::La16 => image::ImageBuffer::from_raw(
+            width,
+            height,
+            samples_from_ne_bytes(&packed, u16::from_ne_bytes),
+        )
+        .map(image::DynamicImage::ImageLumaA16),
+        image::ColorType::Rgb16 => image::ImageBuffer::from_raw(
+            width,
+            height,
+            samples_from_ne_bytes(&packed, u16::from_ne_bytes),
+        )
+        .map(image::DynamicImage::ImageRgb16),
+        image::ColorType::Rgba16 => image::ImageBuffer::from_raw(
+            width,
+            height,
+            samples_from_ne_bytes(&packed, u16::from_ne_bytes),
+        )
+        .map(image::DynamicImage::ImageRgba16),
+        image::ColorType::Rgb32F => image::ImageBuffer::from_raw(
+            width,
+            height,
+            samples_from_ne_bytes(&packed, f32::from_ne_bytes),
+        )
+        .map(image::DynamicImage::ImageRgb32F),
+        image::ColorType::Rgba32F => image::ImageBuffer::from_raw(
+            width,
+            height,
+            samples_from_ne_bytes(&packed, f32::from_ne_bytes),
+        )
+        .map(image::DynamicImage::ImageRgba32F),
+        _ => None,
     }
 }
 
+/// Creates an image from a copy of the caller's pixels. 16-bit and f32 samples are read in native byte order.
+///
+/// stride is the amount of bytes between the start of each row, or 0 if the rows are tightly packed.
+/// Fails with IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if len is too small for the given size, color type and stride.
+#[no_mangle]
+pub extern "C" fn dynamic_image_from_raw(
+    width: u32,
+    height: u32,
+    color: ColorType,
+    data: *const c_void,
+    len: usize,
+    stride: usize,
+) -> LoadFromMemoryResult {
+    ffi_guard(|| {
+        let mismatch = |msg: String| LoadFromMemoryResult {
+            res: std::ptr::null_mut(),
+            err: set_last_error(ErrorType::IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH, msg),
+        };
+        let color = color.to_real();
+        let Some(row_len) = (width as usize).checked_mul(color.bytes_per_pixel() as usize) else {
+            return mismatch(format!("a row of {} pixels is too large", width));
+        };
+        let stride = if stride == 0 { row_len } else { stride };
+        if stride < row_len {
+            return mismatch(format!(
+                "stride is {} bytes, but a row needs {}",
+                stride, row_len
+            ));
+        }
+        let needed = match height {
+            0 => Some(0),
+            h => stride
+                .checked_mul(h as usize - 1)
+                .and_then(|a| a.checked_add(row_len)),
+        };
+        match needed {
+            Some(n) if n <= len => {}
+            _ => {
+                return mismatch(format!(
+                    "a {}x{} {:?} image needs more than the {} bytes given",
+                    width, height, color, len
+                ))
+            }
+        }
+        assert!(!data.is_null() || len == 0);
+        let data = match len {
+            0 => &[][..],
+            _ => unsafe { std::slice::from_raw_parts(data as *const u8, len) },
+        };
+        let packed = pack_rows(data, row_len, stride, height as usize);
+        let img = image_from_packed(width, height, color, packed);
+        match img {
+            Some(a) => LoadFromMemoryResult {
+                res: ravel!({ a }),
+                err: ErrorType::IMAGE_ERROR_NONE,
+            },
+            None => mismatch(format!("could not make a {:?} image from the data", color)),
+        }
+    })
+}
+
 //#[no_mangle]
 //pub extern "C" fn dynamic_image_from_decoder() {}
 
@@ -383,31 +913,51 @@ pub extern "C" fn dynamic_image_adjust_contrast(
     this: *mut DynamicImage,
     c: f32,
 ) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).adjust_contrast(c) })
+    ffi_guard(|| ravel!({ unravel_mut!(this).adjust_contrast(c) }))
 }
+/// Points at the image's own pixel buffer, which stays valid until the image is freed. See also dynamic_image_bytes_view.
 #[no_mangle]
 pub extern "C" fn dynamic_image_as_bytes(this: *mut DynamicImage, count: *mut usize) -> *mut u8 {
-    let b = unravel_mut!(this).as_bytes();
-    unsafe { *count = b.len() };
-    return Box::leak(Box::new(unsafe { *b.as_ptr() }));
+    ffi_guard(|| {
+        let b = pixel::bytes_mut(unravel_mut!(this));
+        unsafe { *count = b.len() };
+        b.as_mut_ptr()
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn dynamic_image_blur(this: *mut DynamicImage, sigma: f32) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).blur(sigma) })
+    ffi_guard(|| ravel!({ unravel_mut!(this).blur(sigma) }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_brighten(this: *mut DynamicImage, value: i32) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).brighten(value) })
+    ffi_guard(|| ravel!({ unravel_mut!(this).brighten(value) }))
+}
+/// Returns a deep copy of the image and its ICC profile, which has to be freed separately.
+#[no_mangle]
+pub extern "C" fn dynamic_image_clone(this: *mut DynamicImage) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let copy = ravel!({ unravel!(this).clone() });
+        let icc = unsafe { this.as_ref() }.unwrap().icc().map(|a| a.to_vec());
+        unsafe { copy.as_mut() }.unwrap().set_icc(icc);
+        copy
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_invert(this: *mut DynamicImage) {
-    unravel_mut!(this).invert();
+    ffi_guard(|| {
+        unravel_mut!(this).invert();
+    })
 }
+impl PanicFallback for ColorType {
+    fn panic_fallback() -> Self {
+        ColorType::COLOR_TYPE_L8
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn dynamic_image_color(this: *mut DynamicImage) -> ColorType {
-    let col = unravel_mut!(this).color() as u32;
-    unsafe { std::mem::transmute(col) }
+    ffi_guard(|| ColorType::from_real(unravel_mut!(this).color()))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_crop(
@@ -417,7 +967,7 @@ pub extern "C" fn dynamic_image_crop(
     width: u32,
     height: u32,
 ) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).crop(x, y, width, height) })
+    ffi_guard(|| ravel!({ unravel_mut!(this).crop(x, y, width, height) }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_crop_imm(
@@ -427,7 +977,7 @@ pub extern "C" fn dynamic_image_crop_imm(
     width: u32,
     height: u32,
 ) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).crop_imm(x, y, width, height) })
+    ffi_guard(|| ravel!({ unravel_mut!(this).crop_imm(x, y, width, height) }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_filter3x3(
@@ -435,25 +985,27 @@ pub extern "C" fn dynamic_image_filter3x3(
     kernel: *mut f32,
     size: usize,
 ) -> *mut DynamicImage {
-    assert!(!kernel.is_null());
-    assert!(kernel.is_aligned());
-    assert!(size < isize::MAX as usize);
-    ravel!({
-        unravel_mut!(this).filter3x3(unsafe { std::slice::from_raw_parts_mut(kernel, size) })
+    ffi_guard(|| {
+        assert!(!kernel.is_null());
+        assert!(kernel.is_aligned());
+        assert!(size < isize::MAX as usize);
+        ravel!({
+            unravel_mut!(this).filter3x3(unsafe { std::slice::from_raw_parts_mut(kernel, size) })
+        })
     })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_fliph(this: *mut DynamicImage) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).fliph() })
+    ffi_guard(|| ravel!({ unravel_mut!(this).fliph() }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_flipv(this: *mut DynamicImage) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).flipv() })
+    ffi_guard(|| ravel!({ unravel_mut!(this).flipv() }))
 }
 
 #[no_mangle]
 pub extern "C" fn dynamic_image_grayscale(this: *mut DynamicImage) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).grayscale() })
+    ffi_guard(|| ravel!({ unravel_mut!(this).grayscale() }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_unsharpen(
@@ -461,32 +1013,35 @@ pub extern "C" fn dynamic_image_unsharpen(
     sigma: f32,
     threshold: i32,
 ) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).unsharpen(sigma, threshold) })
+    ffi_guard(|| ravel!({ unravel_mut!(this).unsharpen(sigma, threshold) }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_width(this: *mut DynamicImage) -> u32 {
-    unravel_mut!(this).width()
+    ffi_guard(|| unravel_mut!(this).width())
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_height(this: *mut DynamicImage) -> u32 {
-    unravel_mut!(this).height()
+    ffi_guard(|| unravel_mut!(this).height())
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_huerotate(
     this: *mut DynamicImage,
     value: i32,
 ) -> *mut DynamicImage {
-    ravel!({ unravel_mut!(this).huerotate(value) })
+    ffi_guard(|| ravel!({ unravel_mut!(this).huerotate(value) }))
 }
+/// Same as dynamic_image_as_bytes. The pointer borrows the image's buffer, so there's nothing to free.
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_bytes(
     this: *const DynamicImage,
     size: *mut usize,
-) -> *mut *const u8 {
-    let dynamic_image = &unravel!(this);
-    let b = dynamic_image.as_bytes();
-    unsafe { *size = b.len() };
-    return Box::leak(Box::new(b.as_ptr()));
+) -> *const u8 {
+    ffi_guard(|| {
+        let dynamic_image = &unravel!(this);
+        let b = dynamic_image.as_bytes();
+        unsafe { *size = b.len() };
+        b.as_ptr()
+    })
 }
 
 #[no_mangle]
@@ -496,8 +1051,11 @@ pub extern "C" fn dynamic_image_resize(
     nheight: u32,
     filter: FilterType,
 ) -> *mut DynamicImage {
-    let filter = unsafe { std::mem::transmute(filter as u8) };
-    ravel!({ unravel!(this).resize(nwidth, nheight, filter) })
+    ffi_guard(|| {
+        let filter =
+            unsafe { std::mem::transmute::<u8, image::imageops::FilterType>(filter as u8) };
+        ravel!({ unravel!(this).resize(nwidth, nheight, filter) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_resize_exact(
@@ -506,8 +1064,11 @@ pub extern "C" fn dynamic_image_resize_exact(
     nheight: u32,
     filter: FilterType,
 ) -> *mut DynamicImage {
-    let filter = unsafe { std::mem::transmute(filter as u8) };
-    ravel!({ unravel!(this).resize_exact(nwidth, nheight, filter) })
+    ffi_guard(|| {
+        let filter =
+            unsafe { std::mem::transmute::<u8, image::imageops::FilterType>(filter as u8) };
+        ravel!({ unravel!(this).resize_exact(nwidth, nheight, filter) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_resize_to_fill(
@@ -516,35 +1077,39 @@ pub extern "C" fn dynamic_image_resize_to_fill(
     nheight: u32,
     filter: FilterType,
 ) -> *mut DynamicImage {
-    let filter = unsafe { std::mem::transmute(filter as u8) };
-    ravel!({ unravel!(this).resize_to_fill(nwidth, nheight, filter) })
+    ffi_guard(|| {
+        let filter =
+            unsafe { std::mem::transmute::<u8, image::imageops::FilterType>(filter as u8) };
+        ravel!({ unravel!(this).resize_to_fill(nwidth, nheight, filter) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_rotate180(this: *mut DynamicImage) -> *mut DynamicImage {
-    ravel!({ unravel!(this).rotate180() })
+    ffi_guard(|| ravel!({ unravel!(this).rotate180() }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_rotate270(this: *mut DynamicImage) -> *mut DynamicImage {
-    ravel!({ unravel!(this).rotate270() })
+    ffi_guard(|| ravel!({ unravel!(this).rotate270() }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_rotate90(this: *mut DynamicImage) -> *mut DynamicImage {
-    ravel!({ unravel!(this).rotate90() })
+    ffi_guard(|| ravel!({ unravel!(this).rotate90() }))
 }
 #[no_mangle]
-pub extern "C" fn dynamic_image_save(
-    this: *mut DynamicImage,
-    path: *const c_char,
-) -> *const c_char {
-    match unravel!(this).save(unsafe { CStr::from_ptr(path) }.to_str().unwrap()) {
-        Ok(_) => std::ptr::null(),
-        Err(er) => unsafe {
-            Box::leak(Box::new(
-                CString::new(format!("{:?}", er).as_str()).unwrap(),
-            ))
-            .as_ptr()
-        },
-    }
+pub extern "C" fn dynamic_image_save(this: *mut DynamicImage, path: *const c_char) -> ErrorType {
+    ffi_guard(|| {
+        let Some(path) = path_from_ptr(path) else {
+            return invalid_path();
+        };
+        let format = match image::ImageFormat::from_path(&path) {
+            Ok(a) => a,
+            Err(er) => return get_image_error(er),
+        };
+        match icc::save_with_icc(unravel!(this), icc::icc_of(this), &path, format) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_save_with_format(
@@ -552,12 +1117,16 @@ pub extern "C" fn dynamic_image_save_with_format(
     path: *const c_char,
     format: ImageFormat,
 ) -> ErrorType {
-    let format = unsafe { std::mem::transmute(format as u8) };
-    match unravel!(this).save_with_format(unsafe { CStr::from_ptr(path) }.to_str().unwrap(), format)
-    {
-        Ok(_) => ErrorType::IMAGE_ERROR_NONE,
-        Err(er) => get_image_error(er),
-    }
+    ffi_guard(|| {
+        let format = unsafe { std::mem::transmute::<u8, image::ImageFormat>(format as u8) };
+        let Some(path) = path_from_ptr(path) else {
+            return invalid_path();
+        };
+        match icc::save_with_icc(unravel!(this), icc::icc_of(this), &path, format) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_thumbnail(
@@ -565,7 +1134,7 @@ pub extern "C" fn dynamic_image_thumbnail(
     nwidth: u32,
     nheight: u32,
 ) -> *mut DynamicImage {
-    ravel!({ unravel!(this).thumbnail(nwidth, nheight) })
+    ffi_guard(|| ravel!({ unravel!(this).thumbnail(nwidth, nheight) }))
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_thumbnail_exact(
@@ -573,58 +1142,78 @@ pub extern "C" fn dynamic_image_thumbnail_exact(
     nwidth: u32,
     nheight: u32,
 ) -> *mut DynamicImage {
-    ravel!({ unravel!(this).thumbnail_exact(nwidth, nheight) })
+    ffi_guard(|| ravel!({ unravel!(this).thumbnail_exact(nwidth, nheight) }))
 }
 
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_luma16(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_luma16()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_luma16()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_luma8(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_luma8()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_luma8()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_luma_alpha16(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_luma_alpha16()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_luma_alpha16()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_luma_alpha8(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_luma_alpha8()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_luma_alpha8()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_rgb16(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_rgb16()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_rgb16()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_rgb32f(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_rgb32f()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_rgb32f()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_rgb8(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_rgb8()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_rgb8()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_rgba16(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_rgba16()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_rgba16()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_rgba32f(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_rgba32f()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_rgba32f()) })
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_into_rgba8(this: *mut DynamicImage) -> *mut DynamicImage {
-    let th = unravel!(this).to_owned();
-    ravel!({ image::DynamicImage::from(th.into_rgba8()) })
+    ffi_guard(|| {
+        let th = unravel!(this).to_owned();
+        ravel!({ image::DynamicImage::from(th.into_rgba8()) })
+    })
 }
 
 #[no_mangle]
@@ -633,21 +1222,65 @@ pub extern "C" fn dynamic_image_write_to(
     w: *mut RustWriter,
     format: ImageFormat,
 ) -> ErrorType {
-    let format = unsafe { std::mem::transmute(format as u8) };
-    match unravel!(this).write_to(unsafe { &mut w.as_mut().unwrap() }, format) {
-        Ok(_) => ErrorType::IMAGE_ERROR_NONE,
-        Err(er) => get_image_error(er),
-    }
+    ffi_guard(|| {
+        let format = unsafe { std::mem::transmute::<u8, image::ImageFormat>(format as u8) };
+        let w = unsafe { w.as_mut().unwrap() };
+        match icc::write_with_icc(unravel!(this), icc::icc_of(this), w, format) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
 }
 #[no_mangle]
 pub extern "C" fn dynamic_image_write_with_encoder(
     this: *mut DynamicImage,
     encoder: *mut ImageEncoder,
 ) -> ErrorType {
-    match unravel!(this).write_with_encoder(unsafe { encoder.as_mut().unwrap() }) {
-        Ok(_) => ErrorType::IMAGE_ERROR_NONE,
-        Err(er) => get_image_error(er),
-    }
+    ffi_guard(
+        || match unravel!(this).write_with_encoder(unsafe { encoder.as_mut().unwrap() }) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        },
+    )
+}
+
+/// Encodes the image into a buffer owned by the library, writing its length to out_len.
+/// Returns NULL on failure, in which case rs_image_last_error_code says why.
+///
+/// The buffer has to be released with rs_image_buffer_free.
+#[no_mangle]
+pub extern "C" fn dynamic_image_encode_to_buffer(
+    this: *mut DynamicImage,
+    format: ImageFormat,
+    out_len: *mut usize,
+) -> *mut u8 {
+    ffi_guard(|| {
+        assert!(!out_len.is_null());
+        unsafe { *out_len = 0 };
+        let format = unsafe { std::mem::transmute::<u8, image::ImageFormat>(format as u8) };
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        match icc::write_with_icc(unravel!(this), icc::icc_of(this), &mut cursor, format) {
+            Ok(_) => {
+                let buf = cursor.into_inner().into_boxed_slice();
+                unsafe { *out_len = buf.len() };
+                Box::into_raw(buf) as *mut u8
+            }
+            Err(er) => {
+                get_image_error(er);
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Frees a buffer returned by dynamic_image_encode_to_buffer. len has to be the length that was given back with it.
+#[no_mangle]
+pub extern "C" fn rs_image_buffer_free(buf: *mut u8, len: usize) {
+    ffi_guard(|| {
+        if !buf.is_null() {
+            std::mem::drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(buf, len)) });
+        }
+    })
 }
 
 #[repr(C)]
@@ -656,9 +1289,98 @@ pub struct Dimensions {
     height: u32,
 }
 
+/// What dynamic_image_probe found out about an image.
+///
+/// format, dimensions and color_type are unspecified unless err is IMAGE_ERROR_NONE. In particular a failed probe
+/// can leave format reading as IMAGE_FORMAT_PNG, so always check err first.
+#[repr(C)]
+pub struct ProbeResult {
+    pub format: ImageFormat,
+    pub dimensions: Dimensions,
+    pub color_type: ExtendedColorType,
+    pub err: ErrorType,
+}
+
+impl ProbeResult {
+    fn error(err: ErrorType) -> Self {
+        ProbeResult {
+            format: ImageFormat::IMAGE_FORMAT_PNG,
+            dimensions: Dimensions {
+                width: 0,
+                height: 0,
+            },
+            color_type: ExtendedColorType::EXTENDED_COLOR_TYPE_UNKNOWN,
+            err,
+        }
+    }
+}
+
+impl PanicFallback for ProbeResult {
+    fn panic_fallback() -> Self {
+        ProbeResult::error(ErrorType::IMAGE_ERROR_PANIC)
+    }
+}
+
+fn probe<R: std::io::BufRead + std::io::Seek>(reader: R) -> ProbeResult {
+    let reader = match image::ImageReader::new(reader).with_guessed_format() {
+        Ok(a) => a,
+        Err(er) => return ProbeResult::error(get_image_error(ImageError::IoError(er))),
+    };
+    let Some(format) = reader.format().and_then(ImageFormat::from_real) else {
+        return ProbeResult::error(set_last_error(
+            ErrorType::IMAGE_ERROR_UNSUPPORTED_FORMAT,
+            "could not guess the image format",
+        ));
+    };
+    match reader.into_decoder() {
+        Ok(decoder) => {
+            let (width, height) = decoder.dimensions();
+            ProbeResult {
+                format,
+                dimensions: Dimensions { width, height },
+                color_type: ExtendedColorType::from_real(decoder.original_color_type()),
+                err: ErrorType::IMAGE_ERROR_NONE,
+            }
+        }
+        Err(er) => ProbeResult::error(get_image_error(er)),
+    }
+}
+
+/// Reads the format, dimensions and color type of an image from its header, without decoding any pixels.
+#[no_mangle]
+pub extern "C" fn dynamic_image_probe(bytes: *const u8, size: usize) -> ProbeResult {
+    ffi_guard(|| {
+        assert!(!bytes.is_null());
+        assert!(bytes.is_aligned());
+        assert!(size < isize::MAX as usize);
+        probe(std::io::Cursor::new(unsafe {
+            std::slice::from_raw_parts(bytes, size)
+        }))
+    })
+}
+
+/// Same as dynamic_image_probe, but pulls the header through a RustReader.
+#[no_mangle]
+pub extern "C" fn dynamic_image_probe_reader(r: *mut RustReader) -> ProbeResult {
+    ffi_guard(|| {
+        assert!(!r.is_null());
+        assert!(r.is_aligned());
+        probe(std::io::BufReader::new(unsafe { r.as_mut() }.unwrap()))
+    })
+}
+
+impl PanicFallback for Dimensions {
+    fn panic_fallback() -> Self {
+        Dimensions {
+            width: 0,
+            height: 0,
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn dynamic_image_dimensions(this: *mut DynamicImage) -> Dimensions {
-    unsafe { std::mem::transmute(unravel!(this).dimensions()) }
+    ffi_guard(|| unsafe { std::mem::transmute(unravel!(this).dimensions()) })
 }
 
 #[repr(C)]
@@ -669,40 +1391,55 @@ pub struct Rgba {
     a: u8,
 }
 
+impl PanicFallback for Rgba {
+    fn panic_fallback() -> Self {
+        Rgba {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn dynamic_image_get_pixel(this: *mut DynamicImage, x: u32, y: u32) -> Rgba {
-    let dynamic_image = unravel!(this);
-    let c = dynamic_image.get_pixel(x, y);
-    let col = c.0;
-    let e = Rgba {
-        r: col[0],
-        g: col[1],
-        b: col[2],
-        a: col[3],
-    };
-    e
+    ffi_guard(|| {
+        let dynamic_image = unravel!(this);
+        let c = dynamic_image.get_pixel(x, y);
+        let col = c.0;
+        Rgba {
+            r: col[0],
+            g: col[1],
+            b: col[2],
+            a: col[3],
+        }
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn dynamic_image_in_bounds(this: *mut DynamicImage, x: u32, y: u32) -> bool {
-    unravel!(this).in_bounds(x, y)
+    ffi_guard(|| unravel!(this).in_bounds(x, y))
 }
 
+/// Iterates over every pixel as a PixelResult. The iterator borrows the image, so free it before the image.
 #[no_mangle]
 pub extern "C" fn dynamic_image_pixels(this: *mut DynamicImage) -> RawIterator {
-    make_raw_iterator(unravel!(this).pixels())
+    ffi_guard(|| make_raw_iterator(unravel!(this).pixels()))
 }
 
 #[no_mangle]
 pub extern "C" fn dynamic_image_free(this: *mut DynamicImage) {
-    if !this.is_null() {
-        let og = unsafe { Box::from_raw(this) };
-        if og.inner != std::ptr::null_mut() {
-            std::mem::drop(unsafe { Box::from_raw(og.inner) });
+    ffi_guard(|| {
+        if !this.is_null() {
+            let og = unsafe { Box::from_raw(this) };
+            if !og.inner.is_null() {
+                std::mem::drop(unsafe { Box::from_raw(og.inner as *mut image::DynamicImage) });
+            }
+            std::mem::drop(og);
+            // otherwise, do nothing. the value doesn't exist.
         }
-        std::mem::drop(og);
-        // otherwise, do nothing. the value doesn't exist.
-    }
+    })
 }
 
 #[repr(C)]
@@ -712,7 +1449,17 @@ pub struct PixelResult {
     pub color: Rgba,
 }
 
+impl PanicFallback for PixelResult {
+    fn panic_fallback() -> Self {
+        PixelResult {
+            x: 0,
+            y: 0,
+            color: Rgba::panic_fallback(),
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn ____() -> PixelResult {
-    unimplemented!()
+    ffi_guard(|| unimplemented!())
 }
@@ -0,0 +1,253 @@
+use image::{imageops, DynamicImage as Real, ImageBuffer, Rgba, Rgba32FImage};
+
+use crate::{ffi_guard, pixel::convert_to, DynamicImage, FilterType};
+
+/// Which values the _with_color_space variants of the resampling and filtering functions work on.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum ColorSpaceMode {
+    /// The encoded values as stored, which is what the plain functions do. Fast, but averaging gamma
+    /// encoded values darkens fine detail and high-contrast edges.
+    COLOR_SPACE_MODE_GAMMA,
+    /// Decodes the sRGB transfer function first, works on linear light in f32, and encodes the result
+    /// back into the original color type. Rgb32F and Rgba32F images are taken to hold linear light already,
+    /// the way the HDR and OpenEXR decoders produce them, so only their processing is done in f32.
+    COLOR_SPACE_MODE_LINEAR,
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    match c <= 0.0031308 {
+        true => c * 12.92,
+        false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+fn map_color(buf: &mut Rgba32FImage, f: fn(f32) -> f32) {
+    for p in buf.pixels_mut() {
+        for c in &mut p.0[..3] {
+            *c = f(*c);
+        }
+    }
+}
+
+fn is_float(img: &Real) -> bool {
+    matches!(
+        img.color(),
+        image::ColorType::Rgb32F | image::ColorType::Rgba32F
+    )
+}
+
+/// Runs f on the image in the given color space and hands back the result in the image's own color type.
+pub(crate) fn in_color_space(
+    img: &Real,
+    mode: ColorSpaceMode,
+    f: impl FnOnce(&Real) -> Real,
+) -> Real {
+    match mode {
+        ColorSpaceMode::COLOR_SPACE_MODE_LINEAR if !is_float(img) => {
+            let mut buf = img.to_rgba32f();
+            map_color(&mut buf, srgb_to_linear);
+            let mut out = f(&Real::from(buf)).into_rgba32f();
+            map_color(&mut out, linear_to_srgb);
+            convert_to(&Real::from(out), img.color())
+        }
+        _ => f(img),
+    }
+}
+
+/// Unsharp masking done in f32, since imageops::unsharpen rounds f32 samples to integers.
+/// Alpha is left as it is.
+fn unsharpen_f32(img: &Real, sigma: f32, threshold: i32) -> Real {
+    let threshold = threshold as f32 / 255.0;
+    let src = img.to_rgba32f();
+    let mut out = img.blur(sigma).into_rgba32f();
+    for (o, p) in out.pixels_mut().zip(src.pixels()) {
+        for i in 0..3 {
+            let diff = p[i] - o[i];
+            o[i] = match diff.abs() > threshold {
+                true => (p[i] + diff).max(0.0),
+                false => p[i],
+            };
+        }
+        o[3] = p[3];
+    }
+    convert_to(&Real::from(out), img.color())
+}
+
+/// Box filters the image to nwidth x nheight, weighting each source pixel by how much of it falls inside the
+/// output pixel. imageops::thumbnail does the same, but rounds f32 samples as if they were integers.
+fn area_average(img: &Rgba32FImage, nwidth: u32, nheight: u32) -> Rgba32FImage {
+    let rows = ImageBuffer::from_fn(nwidth, img.height(), |x, y| {
+        average(|i| *img.get_pixel(i, y), img.width(), nwidth, x)
+    });
+    ImageBuffer::from_fn(nwidth, nheight, |x, y| {
+        average(|i| *rows.get_pixel(x, i), img.height(), nheight, y)
+    })
+}
+
+/// Averages the part of a row or column of len pixels that covers output pixel at out of nlen.
+fn average(get: impl Fn(u32) -> Rgba<f32>, len: u32, nlen: u32, at: u32) -> Rgba<f32> {
+    let scale = len as f64 / nlen as f64;
+    let (start, end) = (at as f64 * scale, (at + 1) as f64 * scale);
+    let mut sum = [0.0; 4];
+    for i in start.floor() as u32..(end.ceil() as u32).min(len) {
+        let weight = end.min(i as f64 + 1.0) - start.max(i as f64);
+        let p = get(i);
+        for c in 0..4 {
+            sum[c] += p[c] as f64 * weight;
+        }
+    }
+    Rgba(sum.map(|a| (a / scale) as f32))
+}
+
+/// The size DynamicImage::thumbnail picks, the largest that keeps the aspect ratio and fits in nwidth x nheight.
+fn fit(width: u32, height: u32, nwidth: u32, nheight: u32) -> (u32, u32) {
+    let ratio = f64::min(nwidth as f64 / width as f64, nheight as f64 / height as f64);
+    let scale = |a: u32| ((a as f64 * ratio).round() as u32).max(1);
+    (scale(width), scale(height))
+}
+
+fn thumbnail(img: &Real, nwidth: u32, nheight: u32, mode: ColorSpaceMode) -> Real {
+    match mode {
+        ColorSpaceMode::COLOR_SPACE_MODE_GAMMA => img.thumbnail_exact(nwidth, nheight),
+        ColorSpaceMode::COLOR_SPACE_MODE_LINEAR => in_color_space(img, mode, |a| {
+            let out = area_average(&a.to_rgba32f(), nwidth, nheight);
+            convert_to(&Real::from(out), a.color())
+        }),
+    }
+}
+
+pub(crate) fn real_filter(filter: FilterType) -> imageops::FilterType {
+    unsafe { std::mem::transmute::<u8, imageops::FilterType>(filter as u8) }
+}
+
+/// Same as dynamic_image_resize, but resamples in the given color space.
+#[no_mangle]
+pub extern "C" fn dynamic_image_resize_with_color_space(
+    this: *mut DynamicImage,
+    nwidth: u32,
+    nheight: u32,
+    filter: FilterType,
+    mode: ColorSpaceMode,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        ravel!({
+            in_color_space(img, mode, |a| {
+                a.resize(nwidth, nheight, real_filter(filter))
+            })
+        })
+    })
+}
+
+/// Same as dynamic_image_resize_exact, but resamples in the given color space.
+#[no_mangle]
+pub extern "C" fn dynamic_image_resize_exact_with_color_space(
+    this: *mut DynamicImage,
+    nwidth: u32,
+    nheight: u32,
+    filter: FilterType,
+    mode: ColorSpaceMode,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        ravel!({
+            in_color_space(img, mode, |a| {
+                a.resize_exact(nwidth, nheight, real_filter(filter))
+            })
+        })
+    })
+}
+
+/// Same as dynamic_image_resize_to_fill, but resamples in the given color space.
+#[no_mangle]
+pub extern "C" fn dynamic_image_resize_to_fill_with_color_space(
+    this: *mut DynamicImage,
+    nwidth: u32,
+    nheight: u32,
+    filter: FilterType,
+    mode: ColorSpaceMode,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        ravel!({
+            in_color_space(img, mode, |a| {
+                a.resize_to_fill(nwidth, nheight, real_filter(filter))
+            })
+        })
+    })
+}
+
+/// Same as dynamic_image_thumbnail, but averages in the given color space.
+#[no_mangle]
+pub extern "C" fn dynamic_image_thumbnail_with_color_space(
+    this: *mut DynamicImage,
+    nwidth: u32,
+    nheight: u32,
+    mode: ColorSpaceMode,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        let (nwidth, nheight) = fit(img.width(), img.height(), nwidth, nheight);
+        ravel!({ thumbnail(img, nwidth, nheight, mode) })
+    })
+}
+
+/// Same as dynamic_image_thumbnail_exact, but averages in the given color space.
+#[no_mangle]
+pub extern "C" fn dynamic_image_thumbnail_exact_with_color_space(
+    this: *mut DynamicImage,
+    nwidth: u32,
+    nheight: u32,
+    mode: ColorSpaceMode,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        ravel!({ thumbnail(img, nwidth, nheight, mode) })
+    })
+}
+
+/// Same as dynamic_image_blur, but blurs in the given color space.
+#[no_mangle]
+pub extern "C" fn dynamic_image_blur_with_color_space(
+    this: *mut DynamicImage,
+    sigma: f32,
+    mode: ColorSpaceMode,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        ravel!({ in_color_space(img, mode, |a| a.blur(sigma)) })
+    })
+}
+
+/// Same as dynamic_image_unsharpen, but sharpens in the given color space.
+///
+/// threshold is still on a 0-255 scale, compared against the difference between the image and its blurred copy in
+/// the chosen color space. In linear mode the sharpening is done in f32 and alpha is left as it is.
+#[no_mangle]
+pub extern "C" fn dynamic_image_unsharpen_with_color_space(
+    this: *mut DynamicImage,
+    sigma: f32,
+    threshold: i32,
+    mode: ColorSpaceMode,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        ravel!({
+            match mode {
+                ColorSpaceMode::COLOR_SPACE_MODE_GAMMA => img.unsharpen(sigma, threshold),
+                ColorSpaceMode::COLOR_SPACE_MODE_LINEAR => {
+                    in_color_space(img, mode, |a| unsharpen_f32(a, sigma, threshold))
+                }
+            }
+        })
+    })
+}
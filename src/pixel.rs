@@ -0,0 +1,753 @@
+use std::ffi::c_void;
+
+use image::{DynamicImage as Real, GenericImage, GenericImageView, ImageBuffer, Pixel};
+
+use crate::{
+    error::set_last_error,
+    ffi_guard, get_image_error,
+    guard::PanicFallback,
+    image_from_packed,
+    iter::{make_raw_iterator, RawIterator},
+    pack_rows,
+    thin::{free_boxed, ThinIterator},
+    ColorType, DynamicImage, ErrorType, ExtendedColorType, Rgba,
+};
+
+impl Rgba {
+    fn to_real(&self) -> image::Rgba<u8> {
+        image::Rgba([self.r, self.g, self.b, self.a])
+    }
+}
+
+/// Converts the image to the given color type, or copies it if it already is that type.
+pub fn convert_to(img: &Real, color: image::ColorType) -> Real {
+    match color {
+        image::ColorType::L8 => Real::from(img.to_luma8()),
+        image::ColorType::La8 => Real::from(img.to_luma_alpha8()),
+        image::ColorType::Rgb8 => Real::from(img.to_rgb8()),
+        image::ColorType::L16 => Real::from(img.to_luma16()),
+        image::ColorType::La16 => Real::from(img.to_luma_alpha16()),
+        image::ColorType::Rgb16 => Real::from(img.to_rgb16()),
+        image::ColorType::Rgba16 => Real::from(img.to_rgba16()),
+        image::ColorType::Rgb32F => Real::from(img.to_rgb32f()),
+        image::ColorType::Rgba32F => Real::from(img.to_rgba32f()),
+        _ => Real::from(img.to_rgba8()),
+    }
+}
+
+/// Matches two images that have the same color type, running body with both of their ImageBuffers.
+macro_rules! with_same_buffers {
+    ($a:expr, $b:expr, |$d:ident, $s:ident| $body:expr, $otherwise:expr) => {
+        match ($a, $b) {
+            (Real::ImageLuma8($d), Real::ImageLuma8($s)) => $body,
+            (Real::ImageLumaA8($d), Real::ImageLumaA8($s)) => $body,
+            (Real::ImageRgb8($d), Real::ImageRgb8($s)) => $body,
+            (Real::ImageRgba8($d), Real::ImageRgba8($s)) => $body,
+            (Real::ImageLuma16($d), Real::ImageLuma16($s)) => $body,
+            (Real::ImageLumaA16($d), Real::ImageLumaA16($s)) => $body,
+            (Real::ImageRgb16($d), Real::ImageRgb16($s)) => $body,
+            (Real::ImageRgba16($d), Real::ImageRgba16($s)) => $body,
+            (Real::ImageRgb32F($d), Real::ImageRgb32F($s)) => $body,
+            (Real::ImageRgba32F($d), Real::ImageRgba32F($s)) => $body,
+            _ => $otherwise,
+        }
+    };
+}
+pub(crate) use with_same_buffers;
+
+/// Copies src into dst at (x, y) without going through 8-bit color, converting src to dst's color type first.
+pub fn copy_into(dst: &mut Real, src: &Real, x: u32, y: u32) -> image::ImageResult<()> {
+    let src = convert_to(src, dst.color());
+    with_same_buffers!(dst, &src, |d, s| d.copy_from(s, x, y), unreachable!())
+}
+
+fn out_of_bounds(img: &Real, x: u32, y: u32, width: u32, height: u32) -> Option<ErrorType> {
+    let fits = x.checked_add(width).is_some_and(|a| a <= img.width())
+        && y.checked_add(height).is_some_and(|a| a <= img.height());
+    match fits {
+        true => None,
+        false => Some(set_last_error(
+            ErrorType::IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH,
+            format!(
+                "a {}x{} region at ({}, {}) does not fit in the {}x{} image",
+                width,
+                height,
+                x,
+                y,
+                img.width(),
+                img.height()
+            ),
+        )),
+    }
+}
+
+/// Copies all of src into dst with its top left corner at (x, y), converting it to dst's color type.
+/// Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if src doesn't fit, in which case dst is left alone.
+#[no_mangle]
+pub extern "C" fn dynamic_image_copy_from(
+    dst: *mut DynamicImage,
+    src: *mut DynamicImage,
+    x: u32,
+    y: u32,
+) -> ErrorType {
+    ffi_guard(|| {
+        if std::ptr::eq(dst, src) {
+            // An image only fits into itself at (0, 0), where copying changes nothing. Going on would borrow the
+            // same image mutably and immutably at once.
+            let img = unravel!(src);
+            return out_of_bounds(img, x, y, img.width(), img.height())
+                .unwrap_or(ErrorType::IMAGE_ERROR_NONE);
+        }
+        let src = unravel!(src);
+        let dst = unravel_mut!(dst);
+        if let Some(er) = out_of_bounds(dst, x, y, src.width(), src.height()) {
+            return er;
+        }
+        match copy_into(dst, src, x, y) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
+}
+
+/// Sets the pixel at (x, y), converting the color to the image's color type.
+/// Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the pixel is out of bounds.
+#[no_mangle]
+pub extern "C" fn dynamic_image_put_pixel(
+    this: *mut DynamicImage,
+    x: u32,
+    y: u32,
+    color: Rgba,
+) -> ErrorType {
+    ffi_guard(|| {
+        let img = unravel_mut!(this);
+        if let Some(er) = out_of_bounds(img, x, y, 1, 1) {
+            return er;
+        }
+        img.put_pixel(x, y, color.to_real());
+        ErrorType::IMAGE_ERROR_NONE
+    })
+}
+
+/// Sets every pixel of the image to the given color.
+#[no_mangle]
+pub extern "C" fn dynamic_image_fill(this: *mut DynamicImage, color: Rgba) {
+    ffi_guard(|| {
+        let img = unravel_mut!(this);
+        let one = convert_to(
+            &Real::ImageRgba8(ImageBuffer::from_pixel(1, 1, color.to_real())),
+            img.color(),
+        );
+        with_same_buffers!(
+            img,
+            &one,
+            |d, s| {
+                let p = *s.get_pixel(0, 0);
+                d.pixels_mut().for_each(|a| *a = p)
+            },
+            unreachable!()
+        )
+    })
+}
+
+impl ExtendedColorType {
+    /// The color type of an image holding these pixels, and whether the red and blue channels have to be swapped to get there.
+    fn to_color_type(&self) -> Option<(image::ColorType, bool)> {
+        Some(match self {
+            ExtendedColorType::EXTENDED_COLOR_TYPE_L8 => (image::ColorType::L8, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_LA8 => (image::ColorType::La8, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_RGB8 => (image::ColorType::Rgb8, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA8 => (image::ColorType::Rgba8, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_L16 => (image::ColorType::L16, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_LA16 => (image::ColorType::La16, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_RGB16 => (image::ColorType::Rgb16, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA16 => (image::ColorType::Rgba16, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_RGB32F => (image::ColorType::Rgb32F, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_RGBA32F => (image::ColorType::Rgba32F, false),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_BGR8 => (image::ColorType::Rgb8, true),
+            ExtendedColorType::EXTENDED_COLOR_TYPE_BGRA8 => (image::ColorType::Rgba8, true),
+            _ => return None,
+        })
+    }
+}
+
+/// Copies a width x height block of the caller's pixels into the image at (x, y), converting them to the image's color type.
+///
+/// src is read in native byte order with stride bytes between the start of each row, or tightly packed if stride is 0.
+/// Only the 8-bit, 16-bit and f32 color types plus BGR8 and BGRA8 are supported as a source.
+/// Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the region doesn't fit in the image or stride is shorter than a
+/// row, and IMAGE_ERROR_PARAMETER_MALFORMED if src is NULL.
+#[no_mangle]
+pub extern "C" fn dynamic_image_write_region(
+    this: *mut DynamicImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    src: *const c_void,
+    stride: usize,
+    color: ExtendedColorType,
+) -> ErrorType {
+    ffi_guard(|| {
+        let img = unravel_mut!(this);
+        let Some((color, swap_rb)) = color.to_color_type() else {
+            return set_last_error(
+                ErrorType::IMAGE_ERROR_UNSUPPORTED_COLOR,
+                "pixels can't be written from this color type",
+            );
+        };
+        if let Some(er) = out_of_bounds(img, x, y, width, height) {
+            return er;
+        }
+        if width == 0 || height == 0 {
+            return ErrorType::IMAGE_ERROR_NONE;
+        }
+        let mismatch =
+            |msg: String| set_last_error(ErrorType::IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH, msg);
+        if src.is_null() {
+            return set_last_error(ErrorType::IMAGE_ERROR_PARAMETER_MALFORMED, "src is NULL");
+        }
+        let bpp = color.bytes_per_pixel() as usize;
+        let row_len = width as usize * bpp;
+        let stride = if stride == 0 { row_len } else { stride };
+        if stride < row_len {
+            return mismatch(format!(
+                "stride is {} bytes, but a row needs {}",
+                stride, row_len
+            ));
+        }
+        let Some(len) = stride
+            .checked_mul(height as usize - 1)
+            .and_then(|a| a.checked_add(row_len))
+        else {
+            return mismatch(format!("a {}x{} region is too large", width, height));
+        };
+        let data = unsafe { std::slice::from_raw_parts(src as *const u8, len) };
+        let mut packed = pack_rows(data, row_len, stride, height as usize);
+        if swap_rb {
+            packed.chunks_exact_mut(bpp).for_each(|a| a.swap(0, 2));
+        }
+        let region = match image_from_packed(width, height, color, packed) {
+            Some(a) => a,
+            None => return mismatch(format!("could not make a {:?} region from the data", color)),
+        };
+        match copy_into(img, &region, x, y) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
+}
+
+/// A 16-bit color, for images with more precision than Rgba can hold.
+#[repr(C)]
+pub struct Rgba16 {
+    r: u16,
+    g: u16,
+    b: u16,
+    a: u16,
+}
+
+/// A floating point color, usually from 0.0 to 1.0 but possibly outside of it for HDR images.
+#[repr(C)]
+pub struct Rgba32F {
+    r: f32,
+    g: f32,
+    b: f32,
+    a: f32,
+}
+
+impl PanicFallback for Rgba16 {
+    fn panic_fallback() -> Self {
+        Rgba16::from_real(image::Rgba([0; 4]))
+    }
+}
+
+impl PanicFallback for Rgba32F {
+    fn panic_fallback() -> Self {
+        Rgba32F::from_real(image::Rgba([0.0; 4]))
+    }
+}
+
+impl Rgba16 {
+    fn from_real(c: image::Rgba<u16>) -> Self {
+        let [r, g, b, a] = c.0;
+        Rgba16 { r, g, b, a }
+    }
+    fn to_real(&self) -> image::Rgba<u16> {
+        image::Rgba([self.r, self.g, self.b, self.a])
+    }
+}
+
+impl Rgba32F {
+    fn from_real(c: image::Rgba<f32>) -> Self {
+        let [r, g, b, a] = c.0;
+        Rgba32F { r, g, b, a }
+    }
+    fn to_real(&self) -> image::Rgba<f32> {
+        image::Rgba([self.r, self.g, self.b, self.a])
+    }
+}
+
+/// Returned by the iterator from dynamic_image_pixels16.
+#[repr(C)]
+pub struct PixelResult16 {
+    pub x: u32,
+    pub y: u32,
+    pub color: Rgba16,
+}
+
+/// Returned by the iterator from dynamic_image_pixels32f.
+#[repr(C)]
+pub struct PixelResult32F {
+    pub x: u32,
+    pub y: u32,
+    pub color: Rgba32F,
+}
+
+/// Iterates over a converted copy of an image, so that it doesn't borrow the image it came from.
+pub struct OwnedPixels<P: Pixel> {
+    buf: ImageBuffer<P, Vec<P::Subpixel>>,
+    i: u64,
+}
+
+impl<P: Pixel> OwnedPixels<P> {
+    fn next_pixel(&mut self) -> Option<(u32, u32, P)> {
+        let (width, height) = self.buf.dimensions();
+        if self.i >= width as u64 * height as u64 {
+            return None;
+        }
+        let (x, y) = (
+            (self.i % width as u64) as u32,
+            (self.i / width as u64) as u32,
+        );
+        self.i += 1;
+        Some((x, y, *self.buf.get_pixel(x, y)))
+    }
+}
+
+impl ThinIterator for OwnedPixels<image::Rgba<u16>> {
+    fn next(&mut self) -> *mut c_void {
+        match self.next_pixel() {
+            Some((x, y, c)) => Box::leak(Box::new(PixelResult16 {
+                x,
+                y,
+                color: Rgba16::from_real(c),
+            })) as *mut _ as *mut c_void,
+            None => std::ptr::null_mut(),
+        }
+    }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<PixelResult16>(item)
+    }
+}
+
+impl ThinIterator for OwnedPixels<image::Rgba<f32>> {
+    fn next(&mut self) -> *mut c_void {
+        match self.next_pixel() {
+            Some((x, y, c)) => Box::leak(Box::new(PixelResult32F {
+                x,
+                y,
+                color: Rgba32F::from_real(c),
+            })) as *mut _ as *mut c_void,
+            None => std::ptr::null_mut(),
+        }
+    }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<PixelResult32F>(item)
+    }
+}
+
+/// A channel of one of the image's pixels, widened or narrowed the way image's own color conversions do it.
+trait Channel: Copy {
+    fn to_u16(self) -> u16;
+    fn to_f32(self) -> f32;
+}
+
+impl Channel for u8 {
+    fn to_u16(self) -> u16 {
+        self as u16 * 257
+    }
+    fn to_f32(self) -> f32 {
+        self as f32 / 255.0
+    }
+}
+
+impl Channel for u16 {
+    fn to_u16(self) -> u16 {
+        self
+    }
+    fn to_f32(self) -> f32 {
+        self as f32 / 65535.0
+    }
+}
+
+impl Channel for f32 {
+    fn to_u16(self) -> u16 {
+        (self.clamp(0.0, 1.0) * 65535.0).round() as u16
+    }
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+/// Reads the pixel at (x, y) straight out of the image's buffer as RGBA, converting each channel with convert.
+macro_rules! rgba_at {
+    ($img:expr, $x:expr, $y:expr, $convert:path) => {
+        match $img {
+            Real::ImageLuma8(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageLumaA8(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageRgb8(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageRgba8(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageLuma16(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageLumaA16(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageRgb16(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageRgba16(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageRgb32F(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            Real::ImageRgba32F(b) => b.get_pixel($x, $y).to_rgba().0.map($convert),
+            other => GenericImageView::get_pixel(other, $x, $y).0.map($convert),
+        }
+    };
+}
+
+/// Returned by dynamic_image_get_pixel16. color is transparent black if err is set.
+#[repr(C)]
+pub struct Pixel16Result {
+    pub color: Rgba16,
+    pub err: ErrorType,
+}
+
+/// Returned by dynamic_image_get_pixel32f. color is transparent black if err is set.
+#[repr(C)]
+pub struct Pixel32FResult {
+    pub color: Rgba32F,
+    pub err: ErrorType,
+}
+
+impl PanicFallback for Pixel16Result {
+    fn panic_fallback() -> Self {
+        Pixel16Result {
+            color: Rgba16::panic_fallback(),
+            err: ErrorType::IMAGE_ERROR_PANIC,
+        }
+    }
+}
+
+impl PanicFallback for Pixel32FResult {
+    fn panic_fallback() -> Self {
+        Pixel32FResult {
+            color: Rgba32F::panic_fallback(),
+            err: ErrorType::IMAGE_ERROR_PANIC,
+        }
+    }
+}
+
+/// Same as dynamic_image_get_pixel, but without losing precision on 16-bit and f32 images.
+/// Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the pixel is out of bounds.
+#[no_mangle]
+pub extern "C" fn dynamic_image_get_pixel16(
+    this: *mut DynamicImage,
+    x: u32,
+    y: u32,
+) -> Pixel16Result {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        match out_of_bounds(img, x, y, 1, 1) {
+            Some(err) => Pixel16Result {
+                color: Rgba16::panic_fallback(),
+                err,
+            },
+            None => Pixel16Result {
+                color: Rgba16::from_real(image::Rgba(rgba_at!(img, x, y, Channel::to_u16))),
+                err: ErrorType::IMAGE_ERROR_NONE,
+            },
+        }
+    })
+}
+
+/// Same as dynamic_image_get_pixel, but without losing precision on 16-bit and f32 images.
+/// Returns IMAGE_ERROR_PARAMETER_DIMENSION_MISMATCH if the pixel is out of bounds.
+#[no_mangle]
+pub extern "C" fn dynamic_image_get_pixel32f(
+    this: *mut DynamicImage,
+    x: u32,
+    y: u32,
+) -> Pixel32FResult {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        match out_of_bounds(img, x, y, 1, 1) {
+            Some(err) => Pixel32FResult {
+                color: Rgba32F::panic_fallback(),
+                err,
+            },
+            None => Pixel32FResult {
+                color: Rgba32F::from_real(image::Rgba(rgba_at!(img, x, y, Channel::to_f32))),
+                err: ErrorType::IMAGE_ERROR_NONE,
+            },
+        }
+    })
+}
+
+/// Same as dynamic_image_put_pixel, but without losing precision on 16-bit and f32 images.
+#[no_mangle]
+pub extern "C" fn dynamic_image_put_pixel16(
+    this: *mut DynamicImage,
+    x: u32,
+    y: u32,
+    color: Rgba16,
+) -> ErrorType {
+    ffi_guard(|| {
+        let img = unravel_mut!(this);
+        if let Some(er) = out_of_bounds(img, x, y, 1, 1) {
+            return er;
+        }
+        let one = Real::ImageRgba16(ImageBuffer::from_pixel(1, 1, color.to_real()));
+        match copy_into(img, &one, x, y) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
+}
+
+/// Same as dynamic_image_put_pixel, but without losing precision on 16-bit and f32 images.
+#[no_mangle]
+pub extern "C" fn dynamic_image_put_pixel32f(
+    this: *mut DynamicImage,
+    x: u32,
+    y: u32,
+    color: Rgba32F,
+) -> ErrorType {
+    ffi_guard(|| {
+        let img = unravel_mut!(this);
+        if let Some(er) = out_of_bounds(img, x, y, 1, 1) {
+            return er;
+        }
+        let one = Real::ImageRgba32F(ImageBuffer::from_pixel(1, 1, color.to_real()));
+        match copy_into(img, &one, x, y) {
+            Ok(_) => ErrorType::IMAGE_ERROR_NONE,
+            Err(er) => get_image_error(er),
+        }
+    })
+}
+
+/// Iterates over every pixel as a PixelResult16. The pixels are converted up front, so later changes to the image aren't seen.
+#[no_mangle]
+pub extern "C" fn dynamic_image_pixels16(this: *mut DynamicImage) -> RawIterator {
+    ffi_guard(|| {
+        make_raw_iterator(OwnedPixels {
+            buf: unravel!(this).to_rgba16(),
+            i: 0,
+        })
+    })
+}
+
+/// Iterates over every pixel as a PixelResult32F. The pixels are converted up front, so later changes to the image aren't seen.
+#[no_mangle]
+pub extern "C" fn dynamic_image_pixels32f(this: *mut DynamicImage) -> RawIterator {
+    ffi_guard(|| {
+        make_raw_iterator(OwnedPixels {
+            buf: unravel!(this).to_rgba32f(),
+            i: 0,
+        })
+    })
+}
+
+/// The samples of the image as bytes, in native byte order for 16-bit and f32 images.
+pub fn bytes_mut(img: &mut Real) -> &mut [u8] {
+    fn cast<T>(s: &mut [T]) -> &mut [u8] {
+        let len = std::mem::size_of_val(s);
+        unsafe { std::slice::from_raw_parts_mut(s.as_mut_ptr() as *mut u8, len) }
+    }
+    match img {
+        Real::ImageLuma8(b) => cast(b),
+        Real::ImageLumaA8(b) => cast(b),
+        Real::ImageRgb8(b) => cast(b),
+        Real::ImageRgba8(b) => cast(b),
+        Real::ImageLuma16(b) => cast(b),
+        Real::ImageLumaA16(b) => cast(b),
+        Real::ImageRgb16(b) => cast(b),
+        Real::ImageRgba16(b) => cast(b),
+        Real::ImageRgb32F(b) => cast(b),
+        Real::ImageRgba32F(b) => cast(b),
+        _ => unreachable!(),
+    }
+}
+
+/// A borrow of the image's pixel buffer, from dynamic_image_bytes_view.
+///
+/// Rows are row_stride bytes apart and 16-bit and f32 samples are in native byte order.
+/// data stays valid until the image is freed or changed by a function that replaces its buffer.
+#[repr(C)]
+pub struct BytesView {
+    pub data: *const u8,
+    pub len: usize,
+    pub row_stride: usize,
+    pub color: ColorType,
+}
+
+/// Same as BytesView, but the pixels can be written through data.
+#[repr(C)]
+pub struct BytesViewMut {
+    pub data: *mut u8,
+    pub len: usize,
+    pub row_stride: usize,
+    pub color: ColorType,
+}
+
+impl PanicFallback for BytesView {
+    fn panic_fallback() -> Self {
+        BytesView {
+            data: std::ptr::null(),
+            len: 0,
+            row_stride: 0,
+            color: ColorType::COLOR_TYPE_L8,
+        }
+    }
+}
+
+impl PanicFallback for BytesViewMut {
+    fn panic_fallback() -> Self {
+        BytesViewMut {
+            data: std::ptr::null_mut(),
+            len: 0,
+            row_stride: 0,
+            color: ColorType::COLOR_TYPE_L8,
+        }
+    }
+}
+
+fn row_stride(img: &Real) -> usize {
+    img.width() as usize * img.color().bytes_per_pixel() as usize
+}
+
+/// Borrows the image's pixels without copying them.
+#[no_mangle]
+pub extern "C" fn dynamic_image_bytes_view(this: *mut DynamicImage) -> BytesView {
+    ffi_guard(|| {
+        let img = unravel!(this);
+        let b = img.as_bytes();
+        BytesView {
+            data: b.as_ptr(),
+            len: b.len(),
+            row_stride: row_stride(img),
+            color: ColorType::from_real(img.color()),
+        }
+    })
+}
+
+/// Borrows the image's pixels without copying them, so that they can be edited in place.
+#[no_mangle]
+pub extern "C" fn dynamic_image_bytes_view_mut(this: *mut DynamicImage) -> BytesViewMut {
+    ffi_guard(|| {
+        let img = unravel_mut!(this);
+        let row_stride = row_stride(img);
+        let color = ColorType::from_real(img.color());
+        let b = bytes_mut(img);
+        BytesViewMut {
+            data: b.as_mut_ptr(),
+            len: b.len(),
+            row_stride,
+            color,
+        }
+    })
+}
+
+/// Pixel data taken out of an image by dynamic_image_into_raw_buffer.
+///
+/// data holds len bytes laid out like a BytesView. The buffer belongs to the caller and has to be
+/// released with rs_image_raw_buffer_free, passing the struct back unchanged.
+#[repr(C)]
+pub struct RawPixelBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+    pub row_stride: usize,
+    pub width: u32,
+    pub height: u32,
+    pub color: ColorType,
+    /// Only meaningful to rs_image_raw_buffer_free.
+    pub capacity: usize,
+}
+
+impl PanicFallback for RawPixelBuffer {
+    fn panic_fallback() -> Self {
+        RawPixelBuffer {
+            data: std::ptr::null_mut(),
+            len: 0,
+            row_stride: 0,
+            width: 0,
+            height: 0,
+            color: ColorType::COLOR_TYPE_L8,
+            capacity: 0,
+        }
+    }
+}
+
+impl RawPixelBuffer {
+    fn take<T>(&mut self, samples: Vec<T>) {
+        let mut samples = std::mem::ManuallyDrop::new(samples);
+        self.data = samples.as_mut_ptr() as *mut u8;
+        self.len = std::mem::size_of_val(samples.as_slice());
+        self.capacity = samples.capacity();
+    }
+
+    /// Rebuilds the Vec<T> the buffer was made from and drops it.
+    unsafe fn drop_as<T>(&self) {
+        let len = self.len / std::mem::size_of::<T>();
+        std::mem::drop(Vec::from_raw_parts(self.data as *mut T, len, self.capacity));
+    }
+}
+
+/// Consumes the image and hands its pixel data to the caller without copying it.
+///
+/// The handle is freed and mustn't be used afterwards, even if this fails.
+#[no_mangle]
+pub extern "C" fn dynamic_image_into_raw_buffer(this: *mut DynamicImage) -> RawPixelBuffer {
+    ffi_guard(|| {
+        assert!(!this.is_null());
+        let handle = unsafe { Box::from_raw(this) };
+        assert!(!handle.inner.is_null());
+        let img = *unsafe { Box::from_raw(handle.inner as *mut Real) };
+        let mut out = RawPixelBuffer {
+            row_stride: row_stride(&img),
+            width: img.width(),
+            height: img.height(),
+            color: ColorType::from_real(img.color()),
+            ..RawPixelBuffer::panic_fallback()
+        };
+        match img {
+            Real::ImageLuma8(b) => out.take(b.into_raw()),
+            Real::ImageLumaA8(b) => out.take(b.into_raw()),
+            Real::ImageRgb8(b) => out.take(b.into_raw()),
+            Real::ImageRgba8(b) => out.take(b.into_raw()),
+            Real::ImageLuma16(b) => out.take(b.into_raw()),
+            Real::ImageLumaA16(b) => out.take(b.into_raw()),
+            Real::ImageRgb16(b) => out.take(b.into_raw()),
+            Real::ImageRgba16(b) => out.take(b.into_raw()),
+            Real::ImageRgb32F(b) => out.take(b.into_raw()),
+            Real::ImageRgba32F(b) => out.take(b.into_raw()),
+            _ => unreachable!(),
+        }
+        out
+    })
+}
+
+/// Frees the pixel data returned by dynamic_image_into_raw_buffer. Does nothing if data is NULL.
+#[no_mangle]
+pub extern "C" fn rs_image_raw_buffer_free(buf: RawPixelBuffer) {
+    ffi_guard(|| {
+        if buf.data.is_null() {
+            return;
+        }
+        unsafe {
+            match buf.color {
+                ColorType::COLOR_TYPE_L16
+                | ColorType::COLOR_TYPE_LA16
+                | ColorType::COLOR_TYPE_RGB16
+                | ColorType::COLOR_TYPE_RGBA16 => buf.drop_as::<u16>(),
+                ColorType::COLOR_TYPE_RGB32F | ColorType::COLOR_TYPE_RGBA32F => {
+                    buf.drop_as::<f32>()
+                }
+                _ => buf.drop_as::<u8>(),
+            }
+        }
+    })
+}
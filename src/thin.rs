@@ -14,12 +14,26 @@ use crate::{iter::RawIterator, PixelResult, Rgba};
 
 pub trait ThinIterator {
     fn next(&mut self) -> *mut c_void;
+    /// Frees an item previously returned by next.
+    ///
+    /// # Safety
+    /// item has to have come from this iterator's next and not have been freed already.
+    unsafe fn free_item(&self, item: *mut c_void);
+}
+
+/// Frees an item that a ThinIterator::next boxed up as a T.
+///
+/// # Safety
+/// item has to be a pointer from Box::<T>::leak.
+pub unsafe fn free_boxed<T>(item: *mut c_void) {
+    std::mem::drop(Box::from_raw(item as *mut T));
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Hash)]
 pub struct ThinIteratorVtable {
     pub next: unsafe fn(*mut ::core::ffi::c_void) -> *mut c_void,
+    pub free_item: unsafe fn(*mut ::core::ffi::c_void, *mut c_void),
     pub drop: unsafe fn(*mut ::core::ffi::c_void),
 }
 
@@ -35,6 +49,7 @@ impl<__ThinTraitObjectMacro_ReprGeneric0: ThinIterator>
 {
     const __THINTRAITOBJECTMACRO_VTABLE: ThinIteratorVtable = ThinIteratorVtable {
         next: Self::__thintraitobjectmacro_thunk_next,
+        free_item: Self::__thintraitobjectmacro_thunk_free_item,
         drop: Self::__thintraitobjectmacro_repr_drop,
     };
     fn __thintraitobjectmacro_repr_create(
@@ -65,6 +80,17 @@ impl<__ThinTraitObjectMacro_ReprGeneric0: ThinIterator>
             .__thintraitobjectmacro_repr_value
             .next()
     }
+    unsafe fn __thintraitobjectmacro_thunk_free_item(
+        __thintraitobjectmacro_arg0: *mut ::core::ffi::c_void,
+        __thintraitobjectmacro_arg1: *mut c_void,
+    ) {
+        (*(__thintraitobjectmacro_arg0
+            as *mut __ThinTraitObjectMacro_ReprForThinIterator<
+                __ThinTraitObjectMacro_ReprGeneric0,
+            >))
+            .__thintraitobjectmacro_repr_value
+            .free_item(__thintraitobjectmacro_arg1)
+    }
 }
 #[repr(transparent)]
 pub struct BoxedThinIterator<'inner>(
@@ -131,11 +157,14 @@ impl<'inner> BoxedThinIterator<'inner> {
         unsafe { &*(self.0.as_ptr() as *mut &'static ThinIteratorVtable) }
     }
 }
-#[allow(clippy::ref_in_deref)]
+#[allow(clippy::needless_borrow)]
 impl ThinIterator for BoxedThinIterator<'_> {
     fn next(&mut self) -> *mut c_void {
         unsafe { ((self.vtable()).next)(self.0.as_ptr() as *mut _) }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        ((self.vtable()).free_item)(self.0.as_ptr() as *mut _, item)
+    }
 }
 impl ::core::ops::Drop for BoxedThinIterator<'_> {
     fn drop(&mut self) {
@@ -170,6 +199,9 @@ impl ThinIterator for Pixels<'_, image::DynamicImage> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<PixelResult>(item)
+    }
 }
 
 impl Iterator for BoxedThinIterator<'_> {
@@ -190,6 +222,9 @@ impl ThinIterator for StepBy<RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for std::iter::Chain<RawIterator, RawIterator> {
@@ -199,6 +234,9 @@ impl ThinIterator for std::iter::Chain<RawIterator, RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for Zip<RawIterator, RawIterator> {
@@ -208,6 +246,9 @@ impl ThinIterator for Zip<RawIterator, RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for Enumerate<RawIterator> {
@@ -217,6 +258,9 @@ impl ThinIterator for Enumerate<RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for Peekable<RawIterator> {
@@ -226,6 +270,9 @@ impl ThinIterator for Peekable<RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for Skip<RawIterator> {
@@ -235,6 +282,9 @@ impl ThinIterator for Skip<RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for Take<RawIterator> {
@@ -244,6 +294,9 @@ impl ThinIterator for Take<RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for Fuse<RawIterator> {
@@ -253,6 +306,9 @@ impl ThinIterator for Fuse<RawIterator> {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, F> ThinIterator for Map<I, F>
@@ -266,6 +322,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, U, F> ThinIterator for FlatMap<I, U, F>
@@ -280,6 +339,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, F> ThinIterator for Filter<I, F>
@@ -293,6 +355,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, F> ThinIterator for FilterMap<I, F>
@@ -306,6 +371,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, F> ThinIterator for SkipWhile<I, F>
@@ -319,6 +387,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, F> ThinIterator for TakeWhile<I, F>
@@ -332,6 +403,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, F> ThinIterator for MapWhile<I, F>
@@ -345,6 +419,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<B, I, F, St> ThinIterator for Scan<I, St, F>
@@ -358,6 +435,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl<I, F> ThinIterator for Inspect<I, F>
@@ -371,6 +451,9 @@ where
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
 
 impl ThinIterator for &mut RawIterator {
@@ -380,4 +463,7 @@ impl ThinIterator for &mut RawIterator {
             None => std::ptr::null_mut(),
         }
     }
+    unsafe fn free_item(&self, item: *mut c_void) {
+        free_boxed::<<Self as Iterator>::Item>(item)
+    }
 }
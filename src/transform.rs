@@ -0,0 +1,479 @@
+use image::{DynamicImage as Real, Rgba32FImage};
+
+use crate::{
+    alpha, error::set_last_error, ffi_guard, pixel::convert_to, DynamicImage, ErrorType,
+    FilterType, Rgba,
+};
+
+/// A 3x3 matrix in row-major order. Here it maps (x, y, 1) between image coordinates,
+/// the ICC code uses it for RGB to XYZ conversions.
+pub(crate) type Matrix = [f64; 9];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [0.0; 9];
+    for row in 0..3 {
+        for col in 0..3 {
+            out[row * 3 + col] = (0..3).map(|i| a[row * 3 + i] * b[i * 3 + col]).sum();
+        }
+    }
+    out
+}
+
+pub(crate) fn invert(m: &Matrix) -> Option<Matrix> {
+    let [a, b, c, d, e, f, g, h, i] = *m;
+    let det = a * (e * i - f * h) - b * (d * i - f * g) + c * (d * h - e * g);
+    if det.abs() < 1e-12 || !det.is_finite() {
+        return None;
+    }
+    Some([
+        (e * i - f * h) / det,
+        (c * h - b * i) / det,
+        (b * f - c * e) / det,
+        (f * g - d * i) / det,
+        (a * i - c * g) / det,
+        (c * d - a * f) / det,
+        (d * h - e * g) / det,
+        (b * g - a * h) / det,
+        (a * e - b * d) / det,
+    ])
+}
+
+fn translate(x: f64, y: f64) -> Matrix {
+    [1.0, 0.0, x, 0.0, 1.0, y, 0.0, 0.0, 1.0]
+}
+
+/// Maps a point through the matrix, or None if it ends up on or behind the horizon of a projective one.
+fn apply(m: &Matrix, x: f64, y: f64) -> Option<(f64, f64)> {
+    let w = m[6] * x + m[7] * y + m[8];
+    if w <= 1e-12 {
+        return None;
+    }
+    Some((
+        (m[0] * x + m[1] * y + m[2]) / w,
+        (m[3] * x + m[4] * y + m[5]) / w,
+    ))
+}
+
+/// The interpolation kernel for a filter and how far it reaches, in pixels.
+fn kernel(filter: FilterType) -> (fn(f32) -> f32, f32) {
+    fn triangle(x: f32) -> f32 {
+        (1.0 - x.abs()).max(0.0)
+    }
+    fn catmull_rom(x: f32) -> f32 {
+        let x = x.abs();
+        match x {
+            _ if x < 1.0 => 1.5 * x * x * x - 2.5 * x * x + 1.0,
+            _ if x < 2.0 => -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0,
+            _ => 0.0,
+        }
+    }
+    fn gaussian(x: f32) -> f32 {
+        (-2.0 * x * x).exp()
+    }
+    fn sinc(x: f32) -> f32 {
+        match x == 0.0 {
+            true => 1.0,
+            false => {
+                let x = x * std::f32::consts::PI;
+                x.sin() / x
+            }
+        }
+    }
+    fn lanczos3(x: f32) -> f32 {
+        match x.abs() < 3.0 {
+            true => sinc(x) * sinc(x / 3.0),
+            false => 0.0,
+        }
+    }
+    match filter {
+        FilterType::FILTER_TYPE_NEAREST => (triangle, 0.5),
+        FilterType::FILTER_TYPE_TRIANGLE => (triangle, 1.0),
+        FilterType::FILTER_TYPE_CATMULL_ROM => (catmull_rom, 2.0),
+        FilterType::FILTER_TYPE_GAUSSIAN => (gaussian, 3.0),
+        FilterType::FILTER_TYPE_LANCZOS3 => (lanczos3, 3.0),
+    }
+}
+
+/// Samples src at (u, v), where pixel (0, 0) covers 0..1. Anything outside of src reads as bg.
+fn sample(
+    src: &Rgba32FImage,
+    u: f64,
+    v: f64,
+    filter: FilterType,
+    bg: image::Rgba<f32>,
+) -> image::Rgba<f32> {
+    let (w, h) = (src.width() as f64, src.height() as f64);
+    if !(u.is_finite() && v.is_finite()) {
+        return bg;
+    }
+    if let FilterType::FILTER_TYPE_NEAREST = filter {
+        return match u >= 0.0 && v >= 0.0 && u < w && v < h {
+            true => *src.get_pixel(u as u32, v as u32),
+            false => bg,
+        };
+    }
+    let (k, support) = kernel(filter);
+    let (px, py) = (u - 0.5, v - 0.5);
+    let support = support as f64;
+    if px + support < 0.0 || py + support < 0.0 || px - support >= w || py - support >= h {
+        return bg;
+    }
+    let (x0, x1) = (
+        (px - support).floor() as i64 + 1,
+        (px + support).floor() as i64,
+    );
+    let (y0, y1) = (
+        (py - support).floor() as i64 + 1,
+        (py + support).floor() as i64,
+    );
+    let mut sum = [0.0f32; 4];
+    let mut total = 0.0;
+    for y in y0..=y1 {
+        let wy = k((py - y as f64) as f32);
+        for x in x0..=x1 {
+            let weight = wy * k((px - x as f64) as f32);
+            if weight == 0.0 {
+                continue;
+            }
+            let p = match x >= 0 && y >= 0 && (x as f64) < w && (y as f64) < h {
+                true => src.get_pixel(x as u32, y as u32),
+                false => &bg,
+            };
+            for (s, c) in sum.iter_mut().zip(p.0) {
+                *s += weight * c;
+            }
+            total += weight;
+        }
+    }
+    match total == 0.0 {
+        true => bg,
+        false => image::Rgba(sum.map(|s| s / total)),
+    }
+}
+
+/// Renders an out_w x out_h image where each pixel is sampled from img at the point inverse maps it to.
+///
+/// The work is done in f32, and the result is converted back to img's color type. With premultiplied set the
+/// sampling is done on premultiplied colors, so transparent pixels don't bleed into their neighbours.
+fn warp(
+    img: &Real,
+    inverse: &Matrix,
+    out_w: u32,
+    out_h: u32,
+    filter: FilterType,
+    bg: image::Rgba<f32>,
+    premultiplied: bool,
+) -> Real {
+    let mut src = img.to_rgba32f();
+    let mut bg = bg;
+    if premultiplied {
+        alpha::premultiply(&mut src);
+        bg = image::Rgba([bg[0] * bg[3], bg[1] * bg[3], bg[2] * bg[3], bg[3]]);
+    }
+    let mut out = Rgba32FImage::from_fn(out_w, out_h, |x, y| {
+        match apply(inverse, x as f64 + 0.5, y as f64 + 0.5) {
+            Some((u, v)) => sample(&src, u, v, filter, bg),
+            None => bg,
+        }
+    });
+    if premultiplied {
+        alpha::unpremultiply(&mut out);
+    }
+    convert_to(&Real::from(out), img.color())
+}
+
+fn fill_color(c: Rgba) -> image::Rgba<f32> {
+    image::Rgba([c.r, c.g, c.b, c.a].map(|c| c as f32 / 255.0))
+}
+
+/// The projective transform taking the unit square onto quad, whose corners go clockwise from the top left.
+fn square_to_quad(quad: &[f64]) -> Option<Matrix> {
+    let [x0, y0, x1, y1, x2, y2, x3, y3] = quad.try_into().ok()?;
+    let (dx3, dy3) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+    if dx3.abs() < 1e-12 && dy3.abs() < 1e-12 {
+        return Some([x1 - x0, x2 - x1, x0, y1 - y0, y2 - y1, y0, 0.0, 0.0, 1.0]);
+    }
+    let (dx1, dy1) = (x1 - x2, y1 - y2);
+    let (dx2, dy2) = (x3 - x2, y3 - y2);
+    let det = dx1 * dy2 - dx2 * dy1;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let g = (dx3 * dy2 - dx2 * dy3) / det;
+    let h = (dx1 * dy3 - dx3 * dy1) / det;
+    Some([
+        x1 - x0 + g * x1,
+        x3 - x0 + h * x3,
+        x0,
+        y1 - y0 + g * y1,
+        y3 - y0 + h * y3,
+        y0,
+        g,
+        h,
+        1.0,
+    ])
+}
+
+fn singular() -> *mut DynamicImage {
+    set_last_error(
+        ErrorType::IMAGE_ERROR_PARAMETER_MALFORMED,
+        "the transform can't be inverted",
+    );
+    std::ptr::null_mut()
+}
+
+fn rotate(
+    img: &Real,
+    degrees: f32,
+    filter: FilterType,
+    bg: Rgba,
+    expand_canvas: bool,
+    premultiplied: bool,
+) -> Real {
+    let (w, h) = (img.width() as f64, img.height() as f64);
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    let (out_w, out_h) = match expand_canvas {
+        // The epsilon keeps right angles from growing by a pixel through rounding error.
+        true => (
+            ((w * cos.abs() + h * sin.abs()) - 1e-6).ceil().max(0.0) as u32,
+            ((w * sin.abs() + h * cos.abs()) - 1e-6).ceil().max(0.0) as u32,
+        ),
+        false => (img.width(), img.height()),
+    };
+    let rotation = [cos, sin, 0.0, -sin, cos, 0.0, 0.0, 0.0, 1.0];
+    let inverse = mul(
+        &mul(&translate(w / 2.0, h / 2.0), &rotation),
+        &translate(-(out_w as f64) / 2.0, -(out_h as f64) / 2.0),
+    );
+    warp(
+        img,
+        &inverse,
+        out_w,
+        out_h,
+        filter,
+        fill_color(bg),
+        premultiplied,
+    )
+}
+
+/// Rotates the image clockwise by degrees around its center, filling the uncovered corners with bg.
+///
+/// If expand_canvas is true the result is made big enough to hold the whole rotated image, otherwise it
+/// keeps the original size and the corners are cut off.
+#[no_mangle]
+pub extern "C" fn dynamic_image_rotate(
+    this: *mut DynamicImage,
+    degrees: f32,
+    filter: FilterType,
+    bg: Rgba,
+    expand_canvas: bool,
+) -> *mut DynamicImage {
+    ffi_guard(|| ravel!({ rotate(unravel!(this), degrees, filter, bg, expand_canvas, false) }))
+}
+
+/// Same as dynamic_image_rotate, but interpolates with premultiplied alpha, which keeps the color of
+/// transparent pixels from showing up as dark fringes along the edges.
+#[no_mangle]
+pub extern "C" fn dynamic_image_rotate_premultiplied(
+    this: *mut DynamicImage,
+    degrees: f32,
+    filter: FilterType,
+    bg: Rgba,
+    expand_canvas: bool,
+) -> *mut DynamicImage {
+    ffi_guard(|| ravel!({ rotate(unravel!(this), degrees, filter, bg, expand_canvas, true) }))
+}
+
+/// Applies the affine transform matrix to the image, rendering the result into an out_w x out_h image.
+///
+/// matrix is the top two rows of a 3x3 matrix, {a, b, c, d, e, f}, mapping a point in the source to
+/// (a*x + b*y + c, d*x + e*y + f) in the result. Parts of the result that don't come from the source are
+/// transparent black. Returns NULL with IMAGE_ERROR_PARAMETER_MALFORMED if the matrix can't be inverted.
+#[no_mangle]
+pub extern "C" fn dynamic_image_affine(
+    this: *mut DynamicImage,
+    matrix: *const f32,
+    out_w: u32,
+    out_h: u32,
+    filter: FilterType,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        assert!(!matrix.is_null());
+        let m = unsafe { std::slice::from_raw_parts(matrix, 6) };
+        let forward = [
+            m[0] as f64,
+            m[1] as f64,
+            m[2] as f64,
+            m[3] as f64,
+            m[4] as f64,
+            m[5] as f64,
+            0.0,
+            0.0,
+            1.0,
+        ];
+        let Some(inverse) = invert(&forward) else {
+            return singular();
+        };
+        let img = unravel!(this);
+        ravel!({
+            warp(
+                img,
+                &inverse,
+                out_w,
+                out_h,
+                filter,
+                image::Rgba([0.0; 4]),
+                false,
+            )
+        })
+    })
+}
+
+/// Maps the quadrilateral src_quad of the image onto an out_width x out_height rectangle, like when straightening
+/// a photographed page. Anything outside the source is transparent black.
+///
+/// src_quad holds the x and y of the corners that end up at the top left, top right, bottom right and bottom left,
+/// in that order, with (0, 0) being the top left corner of the image. Returns NULL with
+/// IMAGE_ERROR_PARAMETER_MALFORMED if three of the corners are on one line.
+#[no_mangle]
+pub extern "C" fn dynamic_image_warp_perspective(
+    this: *mut DynamicImage,
+    src_quad: *const f32,
+    out_width: u32,
+    out_height: u32,
+    filter: FilterType,
+) -> *mut DynamicImage {
+    dynamic_image_warp_perspective_with_fill(
+        this,
+        src_quad,
+        out_width,
+        out_height,
+        filter,
+        Rgba {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        },
+    )
+}
+
+/// Same as dynamic_image_warp_perspective, but fills the parts outside the source with fill.
+#[no_mangle]
+pub extern "C" fn dynamic_image_warp_perspective_with_fill(
+    this: *mut DynamicImage,
+    src_quad: *const f32,
+    out_width: u32,
+    out_height: u32,
+    filter: FilterType,
+    fill: Rgba,
+) -> *mut DynamicImage {
+    ffi_guard(|| {
+        assert!(!src_quad.is_null());
+        let quad: Vec<f64> = unsafe { std::slice::from_raw_parts(src_quad, 8) }
+            .iter()
+            .map(|&a| a as f64)
+            .collect();
+        let Some(to_quad) = square_to_quad(&quad) else {
+            return singular();
+        };
+        let scale = [
+            1.0 / out_width.max(1) as f64,
+            0.0,
+            0.0,
+            0.0,
+            1.0 / out_height.max(1) as f64,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        ];
+        let img = unravel!(this);
+        ravel!({
+            warp(
+                img,
+                &mul(&to_quad, &scale),
+                out_width,
+                out_height,
+                filter,
+                fill_color(fill),
+                false,
+            )
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLEAR: Rgba = Rgba {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+
+    /// A 3x2 image where no two pixels are the same, so anything landing in the wrong place shows.
+    fn sample_image() -> Real {
+        Real::ImageRgba8(image::RgbaImage::from_fn(3, 2, |x, y| {
+            image::Rgba([x as u8 * 80, y as u8 * 120, 7, 255])
+        }))
+    }
+
+    #[test]
+    fn right_angles_match_the_exact_rotations() {
+        let img = sample_image();
+        for filter in [
+            FilterType::FILTER_TYPE_NEAREST,
+            FilterType::FILTER_TYPE_TRIANGLE,
+        ] {
+            assert_eq!(
+                rotate(&img, 90.0, filter, CLEAR, true, false),
+                img.rotate90()
+            );
+            assert_eq!(
+                rotate(&img, -90.0, filter, CLEAR, true, false),
+                img.rotate270()
+            );
+        }
+    }
+
+    #[test]
+    fn translation_moves_every_pixel() {
+        let src = ravel!({ sample_image() });
+        let matrix = [1.0, 0.0, 2.0, 0.0, 1.0, 1.0];
+        let out = dynamic_image_affine(src, matrix.as_ptr(), 5, 3, FilterType::FILTER_TYPE_NEAREST);
+        let (img, moved) = (unravel!(src).to_rgba8(), unravel!(out).to_rgba8());
+        for (x, y, p) in moved.enumerate_pixels() {
+            match x >= 2 && y >= 1 {
+                true => assert_eq!(*p, *img.get_pixel(x - 2, y - 1), "({}, {})", x, y),
+                false => assert_eq!(p.0, [0; 4], "({}, {})", x, y),
+            }
+        }
+        crate::dynamic_image_free(src);
+        crate::dynamic_image_free(out);
+    }
+
+    #[test]
+    fn identity_quad_leaves_the_image_alone() {
+        let unit = square_to_quad(&[0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0]).unwrap();
+        for (a, b) in unit
+            .iter()
+            .zip([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0])
+        {
+            assert!((a - b).abs() < 1e-12, "{:?}", unit);
+        }
+        let src = ravel!({ sample_image() });
+        let quad = [0.0, 0.0, 3.0, 0.0, 3.0, 2.0, 0.0, 2.0];
+        for filter in [
+            FilterType::FILTER_TYPE_NEAREST,
+            FilterType::FILTER_TYPE_TRIANGLE,
+        ] {
+            let out = dynamic_image_warp_perspective(src, quad.as_ptr(), 3, 2, filter);
+            assert_eq!(unravel!(out), unravel!(src));
+            crate::dynamic_image_free(out);
+        }
+        crate::dynamic_image_free(src);
+    }
+}